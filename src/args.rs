@@ -1,8 +1,11 @@
 use semver::{Error as SemverError, Version};
-use std::{path::PathBuf, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr};
 
 use clap::{Args, Parser, Subcommand};
 
+use crate::components::output::OutputFormat;
+use crate::utils::release::ReleaseChannel;
+
 #[derive(Debug, Clone)]
 pub enum VersionEnum {
     Version(Version),
@@ -28,12 +31,12 @@ impl FromStr for VersionEnum {
     }
 }
 
-impl ToString for VersionEnum {
-    fn to_string(&self) -> String {
+impl fmt::Display for VersionEnum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            VersionEnum::Version(version) => version.to_string(),
-            VersionEnum::Latest => "latest".to_string(),
-            VersionEnum::Nightly => "nightly".to_string(),
+            VersionEnum::Version(version) => write!(f, "{}", version),
+            VersionEnum::Latest => write!(f, "latest"),
+            VersionEnum::Nightly => write!(f, "nightly"),
         }
     }
 }
@@ -54,6 +57,14 @@ pub enum RuntipiMainCommand {
     ResetPassword,
     /// Debug your runtipi instance
     Debug,
+    /// Show the health of your runtipi instance
+    Status,
+    /// Backup and restore your runtipi instance
+    Backup(BackupCommand),
+    /// Save the docker images of tipi in an archive to install or update it without internet access
+    ExportImages(ExportImagesArgs),
+    /// Read and change the settings of your instance (state/settings.json)
+    Config(ConfigCommand),
 }
 
 #[derive(Debug, Parser)]
@@ -61,6 +72,9 @@ pub enum RuntipiMainCommand {
 pub struct RuntipiArgs {
     #[clap(subcommand)]
     pub command: RuntipiMainCommand,
+    /// Output format. json prints a single report at the end of the command instead of human readable text
+    #[clap(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
 }
 
 #[derive(Parser, Debug)]
@@ -71,12 +85,58 @@ pub struct StartArgs {
     /// Skip setting file permissions (not recommended)
     #[clap(long)]
     pub no_permissions: bool,
+    /// Maximum time in seconds to wait for all services to be healthy after starting the containers
+    #[clap(long, default_value_t = 180)]
+    pub timeout: u64,
+    /// Do not pull images, use the ones already available eg: loaded from an offline bundle. Missing images are still pulled when starting
+    #[clap(long)]
+    pub no_pull: bool,
 }
 
 #[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct UpdateCommand {
+    #[clap(subcommand)]
+    pub subcommand: Option<UpdateSubcommand>,
     /// The version to update to eg: v2.5.0 or latest
-    pub version: VersionEnum,
+    #[clap(required_unless_present_any = ["check", "from_file"])]
+    pub version: Option<VersionEnum>,
+    /// Only check if a new version is available and print its release notes
+    #[clap(long, conflicts_with_all = ["version", "from_file"])]
+    pub check: bool,
+    /// Update from a release bundle downloaded beforehand, without internet access
+    #[clap(long, value_name = "BUNDLE", conflicts_with = "version")]
+    pub from_file: Option<PathBuf>,
+    /// Path to a custom .env file. Can be relative to the current directory or absolute.
+    #[clap(short, long)]
+    pub env_file: Option<PathBuf>,
+    /// Skip setting file permissions (not recommended)
+    #[clap(long)]
+    pub no_permissions: bool,
+    /// Update even if the release checksum or signature, or the image archives of a bundle, cannot be verified (not recommended)
+    #[clap(long)]
+    pub skip_verification: bool,
+    /// Allow updating to a version older than the installed one
+    #[clap(long)]
+    pub allow_downgrade: bool,
+    /// Release channel used to resolve latest. Defaults to updateChannel in settings.json, or stable
+    #[clap(long, value_enum)]
+    pub channel: Option<ReleaseChannel>,
+    /// Update without asking for confirmation after showing the release notes
+    #[clap(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UpdateSubcommand {
+    /// Go back to the version used before the last update
+    Rollback(RollbackArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct RollbackArgs {
+    /// The version to go back to. Defaults to the most recent snapshot
+    pub version: Option<String>,
     /// Path to a custom .env file. Can be relative to the current directory or absolute.
     #[clap(short, long)]
     pub env_file: Option<PathBuf>,
@@ -106,6 +166,14 @@ pub enum AppSubcommand {
     Update(UpdateApp),
     /// Start all apps
     StartAll(StartAll),
+    /// List installed apps and their status
+    List(ListApps),
+    /// Install an app from the app store
+    Install(InstallApp),
+    /// Backup the data and configuration of an app
+    Backup(BackupApp),
+    /// Restore an app from an archive created by `app backup`
+    Restore(RestoreApp),
 }
 
 #[derive(Debug, Args)]
@@ -140,3 +208,119 @@ pub struct UpdateApp {
 
 #[derive(Debug, Args)]
 pub struct StartAll {}
+
+#[derive(Debug, Args)]
+pub struct ListApps {}
+
+#[derive(Debug, Args)]
+pub struct InstallApp {
+    /// The id of the app to install
+    pub id: String,
+    /// Set a form field value eg: --set NEXTCLOUD_ADMIN_USER=admin. Can be repeated
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+    /// Path to a JSON file containing form field values. Values passed with --set take precedence
+    #[clap(long)]
+    pub values: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupApp {
+    /// The id of the app to backup
+    pub id: String,
+    /// Folder where the archive is written. Defaults to the backups folder of your runtipi installation
+    #[clap(long)]
+    pub destination: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreApp {
+    /// The id of the app to restore
+    pub id: String,
+    /// Path to the archive created by `app backup`
+    pub archive: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupCommand {
+    /// The subcommand to run
+    #[clap(subcommand)]
+    pub subcommand: BackupSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupSubcommand {
+    /// Create a backup archive of your instance
+    Create(CreateBackup),
+    /// Restore your instance from a backup archive
+    Restore(RestoreBackup),
+}
+
+#[derive(Debug, Args)]
+pub struct CreateBackup {
+    /// Folder where the archive is written. Defaults to the backups folder of your runtipi installation
+    #[clap(long)]
+    pub destination: Option<PathBuf>,
+    /// Do not include the app-data folder
+    #[clap(long)]
+    pub exclude_app_data: bool,
+    /// Only include these apps eg: --apps nextcloud,jellyfin
+    #[clap(long, value_delimiter = ',')]
+    pub apps: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreBackup {
+    /// Path to the archive created by `backup create`
+    pub archive: PathBuf,
+    /// Only verify the archive and list what would be overwritten
+    #[clap(long)]
+    pub dry_run: bool,
+    #[clap(flatten)]
+    pub start: StartArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportImagesArgs {
+    /// Folder where the archive and its manifest are written. Defaults to the current directory
+    #[clap(long)]
+    pub destination: Option<PathBuf>,
+    /// Pull the images before saving them
+    #[clap(long)]
+    pub pull: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigCommand {
+    /// The subcommand to run
+    #[clap(subcommand)]
+    pub subcommand: ConfigSubcommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigSubcommand {
+    /// Print the value of a setting
+    Get(ConfigKey),
+    /// Change the value of a setting
+    Set(SetConfig),
+    /// Remove a setting, going back to its default value
+    Unset(ConfigKey),
+    /// List all settings with their value and default
+    List,
+    /// Check settings.json for invalid values
+    Validate,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigKey {
+    /// Name of the setting eg: port or releaseSource.url
+    pub key: String,
+}
+
+#[derive(Debug, Args)]
+pub struct SetConfig {
+    /// Name of the setting eg: port or releaseSource.url
+    pub key: String,
+    /// New value of the setting
+    pub value: String,
+}
@@ -1,168 +1,553 @@
-use std::io::Error;
+use std::path::{Path, PathBuf};
 
-use crate::args::{AppCommand, AppSubcommand};
-use crate::utils::env::get_env_value;
-use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use serde::{Deserialize, Serialize};
+use colored::Colorize;
+use prettytable::{format, row, Table};
+use serde::Serialize;
 
-use crate::components::spinner;
-use reqwest::blocking::{Client, Response};
+use crate::args::{AppCommand, AppSubcommand, BackupApp, InstallApp, RestoreApp};
+use crate::components::{output, spinner};
+use crate::error::RuntipiError;
+use crate::utils::app_form;
+use crate::utils::backup::{self, ArchiveWriter, BackupManifest, MANIFEST_FORMAT_VERSION};
+use crate::utils::docker::{self, Container};
+use crate::utils::env::{self, env_string_to_map};
+use crate::utils::schemas::AppInfo;
+use crate::utils::system;
+use crate::utils::worker_api::{AppActionResponse, InstallAppRequest, WorkerApi, WorkerApiError};
 
-pub fn run(args: AppCommand) {
-    let base_url = "http://localhost/worker-api/apps";
+pub fn run(args: AppCommand) -> Result<(), RuntipiError> {
+    if let AppSubcommand::List(_) = args.subcommand {
+        return list();
+    }
+
+    let api = WorkerApi::from_current_dir()?;
 
     match args.subcommand {
         AppSubcommand::Start(args) => {
             let spin = spinner::new(&format!("Starting app {}...", args.id));
-            let api_response = api_request(format!("{}/{}/{}", base_url, args.id, "start"));
-            let error_message = format!("Failed to start app {}. See logs/error.log for more details.", args.id);
-
-            match api_response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        spin.succeed("App started successfully!");
-                    } else {
-                        spin.fail(&error_message);
-                    }
-                }
-                Err(err) => {
-                    spin.fail("Failed to start app.");
-                    println!("{}", format!("Error: {}", err));
-                }
-            }
+            let result = report(
+                &spin,
+                api.start_app(&args.id),
+                "App started successfully!",
+                &format!("Failed to start app {}.", args.id),
+            );
             spin.finish();
+            result
         }
         AppSubcommand::Stop(args) => {
             let spin = spinner::new(&format!("Stopping app {}...", args.id));
-            let api_response = api_request(format!("{}/{}/{}", base_url, args.id, "stop"));
-            let error_message = format!("Failed to stop app {}. See logs/error.log for more details.", args.id);
-
-            match api_response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        spin.succeed("App stopped successfully!");
-                    } else {
-                        spin.fail(&error_message);
-                    }
-                }
-                Err(err) => {
-                    spin.fail("Failed to stop app.");
-                    println!("{}", format!("Error: {}", err));
-                }
-            }
+            let result = report(
+                &spin,
+                api.stop_app(&args.id),
+                "App stopped successfully!",
+                &format!("Failed to stop app {}.", args.id),
+            );
             spin.finish();
+            result
         }
         AppSubcommand::Uninstall(args) => {
             let spin = spinner::new(&format!("Uninstalling app {}...", args.id));
-            let api_response = api_request(format!("{}/{}/{}", base_url, args.id, "uninstall"));
-            let error_message = format!("Failed to uninstall app {}. See logs/error.log for more details.", args.id);
-
-            match api_response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        spin.succeed("App uninstalled successfully!");
-                    } else {
-                        spin.fail(&error_message);
-                    }
-                }
-                Err(err) => {
-                    spin.fail("Failed to uninstall app.");
-                    println!("{}", format!("Error: {}", err));
-                }
-            }
+            let result = report(
+                &spin,
+                api.uninstall_app(&args.id),
+                "App uninstalled successfully!",
+                &format!("Failed to uninstall app {}.", args.id),
+            );
             spin.finish();
+            result
         }
         AppSubcommand::Reset(args) => {
             let spin = spinner::new(&format!("Resetting app {}...", args.id));
-            let api_response = api_request(format!("{}/{}/{}", base_url, args.id, "reset"));
-            let error_message = format!("Failed to reset app {}. See logs/error.log for more details.", args.id);
-
-            match api_response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        spin.succeed("App reset successfully!");
-                        spin.finish();
-                    } else {
-                        spin.fail(&error_message);
-                        spin.finish();
-                    }
-                }
-                Err(err) => {
-                    spin.fail("Failed to reset app.");
-                    spin.finish();
-                    println!("{}", format!("Error: {}", err));
-                }
-            }
+            let result = report(
+                &spin,
+                api.reset_app(&args.id),
+                "App reset successfully!",
+                &format!("Failed to reset app {}.", args.id),
+            );
+            spin.finish();
+            result
         }
         AppSubcommand::Update(args) => {
             let spin = spinner::new(&format!("Updating app {}...", args.id));
-            let api_response = api_request(format!("{}/{}/{}", base_url, args.id, "update"));
-            let error_message = format!("Failed to update app {}. See logs/error.log for more details.", args.id);
-
-            match api_response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        spin.succeed("App updated successfully!");
-                        spin.finish();
-                    } else {
-                        spin.fail(&error_message);
-                        spin.finish();
-                    }
-                }
-                Err(err) => {
-                    spin.fail("Failed to update app.");
-                    spin.finish();
-                    println!("{}", format!("Error: {}", err));
-                }
-            }
+            let result = report(
+                &spin,
+                api.update_app(&args.id),
+                "App updated successfully!",
+                &format!("Failed to update app {}.", args.id),
+            );
+            spin.finish();
+            result
         }
         AppSubcommand::StartAll(_) => {
             let spin = spinner::new("Starting all apps...");
-            let api_response = api_request(format!("{}/{}", base_url, "start-all"));
-            let error_message = format!("Failed to start apps. See logs/error.log for more details.");
-
-            match api_response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        spin.succeed("All apps started successfully!!");
-                        spin.finish();
-                    } else {
-                        spin.fail(&error_message);
-                        spin.finish();
-                    }
-                }
-                Err(err) => {
-                    spin.fail("Failed to start apps.");
-                    spin.finish();
-                    println!("{}", format!("Error: {}", err));
-                }
+            let result = report(&spin, api.start_all_apps(), "All apps started successfully!", "Failed to start apps.");
+            spin.finish();
+            result
+        }
+        AppSubcommand::Install(args) => install(&api, args),
+        AppSubcommand::Backup(args) => backup(&api, args),
+        AppSubcommand::Restore(args) => restore(&api, args),
+        AppSubcommand::List(_) => unreachable!(),
+    }
+}
+
+fn report(
+    spin: &spinner::CustomSpinner,
+    result: Result<AppActionResponse, WorkerApiError>,
+    success_message: &str,
+    error_message: &str,
+) -> Result<(), RuntipiError> {
+    match result {
+        Ok(response) if response.success => {
+            spin.succeed(success_message);
+            Ok(())
+        }
+        Ok(response) => {
+            spin.fail(error_message);
+            Err(WorkerApiError::Failed(response.message).into())
+        }
+        Err(err) => {
+            spin.fail(error_message);
+            Err(err.into())
+        }
+    }
+}
+
+// Folders of the root folder holding the files of an app, relative to the app id eg: app-data/<app-id>
+const APP_FOLDERS: [&str; 3] = ["apps", "app-data", "user-config"];
+
+#[derive(Debug, Serialize)]
+struct AppListEntry {
+    id: String,
+    name: String,
+    installed_version: String,
+    available_version: String,
+    update_available: bool,
+    status: String,
+}
+
+fn read_app_info(config_path: &Path) -> Option<AppInfo> {
+    let config = std::fs::read_to_string(config_path).ok()?;
+    serde_json::from_str(&config).ok()
+}
+
+fn format_version(info: &AppInfo) -> String {
+    match (&info.version, info.tipi_version) {
+        (Some(version), Some(tipi_version)) => format!("{} ({})", version, tipi_version),
+        (Some(version), None) => version.clone(),
+        (None, Some(tipi_version)) => format!("({})", tipi_version),
+        (None, None) => "Unknown".to_string(),
+    }
+}
+
+/**
+* Aggregate the state of all containers belonging to an app into a single status
+*/
+fn app_status(app_id: &str, containers: &[Container]) -> String {
+    let app_containers: Vec<&Container> = containers
+        .iter()
+        .filter(|c| c.project.as_deref() == Some(app_id) || c.name == app_id)
+        .collect();
+
+    if app_containers.is_empty() {
+        return "stopped".to_string();
+    }
+
+    if app_containers.iter().all(|c| c.is_running()) {
+        "running".to_string()
+    } else if app_containers.iter().any(|c| c.state == "exited" || c.state == "dead") {
+        "exited".to_string()
+    } else {
+        "stopped".to_string()
+    }
+}
+
+fn list() -> Result<(), RuntipiError> {
+    let root_folder = std::env::current_dir()?;
+    let apps_folder = root_folder.join("apps");
+
+    let app_ids = system::list_installed_apps(&root_folder)
+        .map_err(|e| RuntipiError::Other(format!("Unable to read apps folder {}: {}", apps_folder.display(), e)))?;
+
+    if app_ids.is_empty() {
+        output::info("No apps installed");
+        output::data("apps", Vec::<AppListEntry>::new());
+        return Ok(());
+    }
+
+    let env_file = std::fs::read_to_string(root_folder.join(".env")).unwrap_or_default();
+    let apps_repo_id = env_string_to_map(&env_file).get("APPS_REPO_ID").cloned();
+
+    let containers = docker::list_containers().unwrap_or_else(|e| {
+        output::info(&format!("{} Unable to get container status: {}\n", "⚠️".yellow(), e));
+        vec![]
+    });
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(row!["ID", "Name", "Installed version", "Available version", "Status"]);
+    let mut entries = vec![];
+
+    for app_id in app_ids {
+        let installed = read_app_info(&apps_folder.join(&app_id).join("config.json"));
+        let available = apps_repo_id
+            .as_ref()
+            .and_then(|repo_id| read_app_info(&root_folder.join("repos").join(repo_id).join("apps").join(&app_id).join("config.json")));
+
+        let name = installed.as_ref().and_then(|i| i.name.clone()).unwrap_or_default();
+        let installed_version = installed.as_ref().map(format_version).unwrap_or("Unknown".to_string());
+        let update_available = match (&installed, &available) {
+            (Some(installed), Some(available)) => available.tipi_version > installed.tipi_version,
+            _ => false,
+        };
+        let available_version = available.as_ref().map(format_version).unwrap_or("Unknown".to_string());
+        let status = app_status(&app_id, &containers);
+
+        table.add_row(row![
+            app_id,
+            name,
+            installed_version,
+            if update_available {
+                available_version.yellow()
+            } else {
+                available_version.normal()
+            },
+            match status.as_str() {
+                "running" => status.green(),
+                "exited" => status.red(),
+                _ => status.normal(),
             }
+        ]);
+
+        entries.push(AppListEntry {
+            id: app_id,
+            name,
+            installed_version,
+            available_version,
+            update_available,
+            status,
+        });
+    }
+
+    if output::is_json() {
+        output::data("apps", entries);
+    } else {
+        table.printstd();
+    }
+
+    Ok(())
+}
+
+fn install(api: &WorkerApi, args: InstallApp) -> Result<(), RuntipiError> {
+    let root_folder = std::env::current_dir()?;
+    let env_file = std::fs::read_to_string(root_folder.join(".env")).unwrap_or_default();
+
+    let apps_repo_id = match env_string_to_map(&env_file).get("APPS_REPO_ID") {
+        Some(repo_id) => repo_id.clone(),
+        None => {
+            return Err(RuntipiError::Env(
+                "APPS_REPO_ID is not set in your .env file. Make sure tipi has been started at least once".to_string(),
+            ));
+        }
+    };
+
+    let config_path = root_folder
+        .join("repos")
+        .join(apps_repo_id)
+        .join("apps")
+        .join(&args.id)
+        .join("config.json");
+    let app_info = match read_app_info(&config_path) {
+        Some(app_info) => app_info,
+        None => {
+            return Err(RuntipiError::InvalidInput(format!(
+                "App {} not found in the app store ({})",
+                args.id,
+                config_path.display()
+            )));
+        }
+    };
+
+    let mut values = match args.values {
+        Some(path) => app_form::read_values_file(&path).map_err(RuntipiError::InvalidInput)?,
+        None => serde_json::Map::new(),
+    };
+    values.extend(app_form::parse_set_values(&args.set).map_err(RuntipiError::InvalidInput)?);
+
+    let form = app_form::build_form(&app_info.form_fields, &values)
+        .map_err(|errors| RuntipiError::InvalidInput(format!("Invalid values for app {}:\n  - {}", args.id, errors.join("\n  - "))))?;
+
+    let spin = spinner::new(&format!("Installing app {}...", args.id));
+    let result = report(
+        &spin,
+        api.install_app(&args.id, &InstallAppRequest { form: &form }),
+        "App installed successfully!",
+        &format!("Failed to install app {}.", args.id),
+    );
+    spin.finish();
+
+    result
+}
+
+fn app_paths(app_id: &str) -> Vec<String> {
+    APP_FOLDERS.iter().map(|folder| format!("{}/{}", folder, app_id)).collect()
+}
+
+/**
+* Folder an app path is relative to. app-data lives in the storage folder, the rest in the root folder
+*/
+fn base_folder(root_folder: &Path, app_path: &str) -> PathBuf {
+    if app_path.starts_with("app-data/") {
+        env::storage_folder(root_folder)
+    } else {
+        root_folder.to_path_buf()
+    }
+}
+
+/**
+* Replace the folders of an app by the ones extracted in the staging folder, app-data going to the storage folder
+*/
+fn restore_app_files(root_folder: &Path, staging: &Path, paths: &[String]) -> Result<(), std::io::Error> {
+    paths
+        .iter()
+        .try_for_each(|path| backup::replace_paths(&base_folder(root_folder, path), staging, std::slice::from_ref(path)))
+}
+
+fn ensure_installed(root_folder: &Path, app_id: &str) -> Result<(), RuntipiError> {
+    // The id ends up in paths that are removed on restore, it must not point outside of the app folders
+    if app_id.is_empty()
+        || !app_id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    {
+        return Err(RuntipiError::InvalidInput(format!(
+            "Invalid app id '{}'. Expected lowercase letters, digits, - and _ only",
+            app_id
+        )));
+    }
+
+    if !root_folder.join("apps").join(app_id).is_dir() {
+        return Err(RuntipiError::InvalidInput(format!("App {} is not installed", app_id)));
+    }
+
+    Ok(())
+}
+
+fn is_app_running(app_id: &str) -> Result<bool, RuntipiError> {
+    let containers = docker::running_project_containers(&[app_id.to_string()]).map_err(|e| RuntipiError::Docker(e.to_string()))?;
+
+    Ok(!containers.is_empty())
+}
+
+fn write_app_archive(root_folder: &Path, archive_path: &Path, app_id: &str) -> Result<BackupManifest, RuntipiError> {
+    let mut writer = ArchiveWriter::create(archive_path)?;
+
+    for path in app_paths(app_id) {
+        let source = base_folder(root_folder, &path).join(&path);
+        if source.is_dir() {
+            writer.add_dir(&source, &path, &|_| false)?;
         }
     }
+
+    let manifest = BackupManifest {
+        format_version: MANIFEST_FORMAT_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        tipi_version: std::fs::read_to_string(root_folder.join("VERSION"))?.trim().to_string(),
+        cli_version: env!("CARGO_PKG_VERSION").to_string(),
+        app_id: Some(app_id.to_string()),
+        includes_app_data: true,
+        apps: Some(vec![app_id.to_string()]),
+        database_dump: None,
+        checksums: Default::default(),
+    };
+
+    Ok(writer.finish(manifest)?)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
+fn backup(api: &WorkerApi, args: BackupApp) -> Result<(), RuntipiError> {
+    let root_folder = std::env::current_dir()?;
+    ensure_installed(&root_folder, &args.id)?;
+
+    let destination = args.destination.unwrap_or_else(|| backup::default_backup_folder(&root_folder));
+    std::fs::create_dir_all(&destination)?;
+
+    let archive_path = destination.join(backup::archive_name(&format!("{}-backup", args.id)));
+    let partial_path = archive_path.with_extension("partial");
+
+    let was_running = is_app_running(&args.id)?;
+
+    let spin = spinner::new(&format!("Stopping app {}...", args.id));
+
+    if was_running {
+        let stopped = report(&spin, api.stop_app(&args.id), "App stopped", &format!("Failed to stop app {}.", args.id));
+        if let Err(e) = stopped {
+            spin.finish();
+            return Err(e);
+        }
+    }
+
+    spin.set_message("Creating archive...");
+
+    let result = write_app_archive(&root_folder, &partial_path, &args.id)
+        .and_then(|manifest| Ok(std::fs::rename(&partial_path, &archive_path).map(|_| manifest)?));
+
+    match &result {
+        Ok(_) => spin.succeed(&format!("Backup created at {}", archive_path.display())),
+        Err(_) => {
+            let _ = std::fs::remove_file(&partial_path);
+            spin.fail("Failed to create backup");
+        }
+    }
+
+    // Restart the app even if the backup failed so that it is left as we found it
+    let restarted = if was_running {
+        spin.set_message(&format!("Starting app {}...", args.id));
+        report(
+            &spin,
+            api.start_app(&args.id),
+            "App started",
+            &format!("Failed to start app {}.", args.id),
+        )
+    } else {
+        Ok(())
+    };
+
+    spin.finish();
+
+    let manifest = result?;
+    restarted?;
+
+    output::data("archive", archive_path.display().to_string());
+    output::data("files", manifest.checksums.len());
+
+    Ok(())
 }
 
-fn api_request(url: String) -> Result<Response, Error> {
-    let client = Client::builder().user_agent("reqwest").build().unwrap();
+fn restore(api: &WorkerApi, args: RestoreApp) -> Result<(), RuntipiError> {
+    let root_folder = std::env::current_dir()?;
+    ensure_installed(&root_folder, &args.id)?;
 
-    let claims = Claims { sub: "1".to_string() };
+    let spin = spinner::new("Verifying archive...");
 
-    let encoding_key = EncodingKey::from_secret(get_env_value("JWT_SECRET").unwrap_or("secret".to_string()).as_ref());
-    let token = match encode(&Header::new(Algorithm::HS256), &claims, &encoding_key) {
-        Ok(t) => t,
-        Err(err) => panic!("Error creating token: {:?}", err),
+    let staging = tempfile::Builder::new().prefix(".restore").tempdir_in(&root_folder)?;
+
+    let content = match backup::extract_archive(&args.archive, staging.path()) {
+        Ok(content) => content,
+        Err(e) => {
+            spin.fail("Invalid backup archive");
+            spin.finish();
+            return Err(RuntipiError::InvalidInput(format!("{}: {}", args.archive.display(), e)));
+        }
     };
 
-    let auth_token = format!("Bearer {}", token);
-    let response = client.post(url).header("Authorization", auth_token).send().unwrap();
+    if content.manifest.app_id.as_deref() != Some(args.id.as_str()) {
+        spin.fail("Invalid backup archive");
+        spin.finish();
+        return Err(RuntipiError::InvalidInput(format!(
+            "{} is not a backup of the app {}",
+            args.archive.display(),
+            args.id
+        )));
+    }
+
+    spin.succeed(&format!("Archive verified ({} files)", content.manifest.checksums.len()));
+
+    let was_running = is_app_running(&args.id)?;
 
-    if response.status().is_success() {
-        return Ok(response);
+    if was_running {
+        spin.set_message(&format!("Stopping app {}...", args.id));
+        let stopped = report(&spin, api.stop_app(&args.id), "App stopped", &format!("Failed to stop app {}.", args.id));
+        if let Err(e) = stopped {
+            spin.finish();
+            return Err(e);
+        }
+    }
+
+    spin.set_message("Restoring files...");
+
+    let paths = app_paths(&args.id);
+    if let Err(e) = restore_app_files(&root_folder, staging.path(), &paths) {
+        spin.fail("Failed to restore files");
+        spin.finish();
+        return Err(e.into());
     }
 
-    Err(Error::new(std::io::ErrorKind::Other, format!("Error: {}", response.status())))
+    spin.succeed(&format!("Restored {}", paths.join(", ")));
+
+    let result = if was_running {
+        spin.set_message(&format!("Starting app {}...", args.id));
+        report(
+            &spin,
+            api.start_app(&args.id),
+            "App started",
+            &format!("Failed to start app {}.", args.id),
+        )
+    } else {
+        Ok(())
+    };
+
+    spin.finish();
+
+    output::data("restored", &paths);
+    output::data("tipi_version", &content.manifest.tipi_version);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn validates_app_ids() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("apps/nextcloud")).unwrap();
+
+        assert!(ensure_installed(root.path(), "nextcloud").is_ok());
+        assert!(matches!(ensure_installed(root.path(), "jellyfin"), Err(RuntipiError::InvalidInput(_))));
+
+        for app_id in ["", "..", "../state", "next/cloud", "NextCloud"] {
+            assert!(
+                matches!(ensure_installed(root.path(), app_id), Err(RuntipiError::InvalidInput(_))),
+                "{}",
+                app_id
+            );
+        }
+    }
+
+    #[test]
+    fn restores_an_app_with_a_separate_storage_folder() {
+        let root = tempfile::tempdir().unwrap();
+        // tmpfs is usually another filesystem than the temp folder, like a STORAGE_PATH mount
+        let storage = tempfile::tempdir_in("/dev/shm").or_else(|_| tempfile::tempdir()).unwrap();
+        fs::write(root.path().join(".env"), format!("STORAGE_PATH={}\n", storage.path().display())).unwrap();
+        fs::write(root.path().join("VERSION"), "v3.7.1\n").unwrap();
+        fs::create_dir_all(root.path().join("apps/nextcloud")).unwrap();
+        fs::write(root.path().join("apps/nextcloud/config.json"), "backed up").unwrap();
+        fs::create_dir_all(storage.path().join("app-data/nextcloud/data")).unwrap();
+        fs::write(storage.path().join("app-data/nextcloud/data/file"), "backed up").unwrap();
+
+        let archive = root.path().join("nextcloud.tar.gz");
+        write_app_archive(root.path(), &archive, "nextcloud").unwrap();
+
+        // Botched update: the files change and the app-data gets a new file
+        fs::write(root.path().join("apps/nextcloud/config.json"), "updated").unwrap();
+        fs::write(storage.path().join("app-data/nextcloud/data/file"), "updated").unwrap();
+        fs::write(storage.path().join("app-data/nextcloud/data/new"), "updated").unwrap();
+
+        let staging = tempfile::Builder::new().prefix(".restore").tempdir_in(root.path()).unwrap();
+        let content = backup::extract_archive(&archive, staging.path()).unwrap();
+        assert_eq!(content.manifest.app_id.as_deref(), Some("nextcloud"));
+
+        restore_app_files(root.path(), staging.path(), &app_paths("nextcloud")).unwrap();
+
+        assert_eq!(fs::read_to_string(root.path().join("apps/nextcloud/config.json")).unwrap(), "backed up");
+        assert_eq!(
+            fs::read_to_string(storage.path().join("app-data/nextcloud/data/file")).unwrap(),
+            "backed up"
+        );
+        assert!(!storage.path().join("app-data/nextcloud/data/new").exists());
+        assert!(!root.path().join("app-data").exists());
+    }
 }
@@ -0,0 +1,543 @@
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::args::{BackupCommand, BackupSubcommand, CreateBackup, RestoreBackup};
+use crate::commands::{start, stop, update};
+use crate::components::{output, spinner};
+use crate::error::RuntipiError;
+use crate::utils::backup::{self, ArchiveContent, ArchiveWriter, BackupManifest, DATABASE_DUMP_NAME, MANIFEST_FORMAT_VERSION};
+use crate::utils::{docker, env, release, system};
+
+// Files and folders of the root folder included in a full backup. app-data is read from the storage folder
+const BACKUP_ITEMS: [&str; 6] = [".env", "state", "user-config", "app-data", "apps", "traefik"];
+
+fn is_app_data(path: &str) -> bool {
+    path == "app-data" || path.starts_with("app-data/")
+}
+
+pub fn run(args: BackupCommand) -> Result<(), RuntipiError> {
+    match args.subcommand {
+        BackupSubcommand::Create(args) => create(args),
+        BackupSubcommand::Restore(args) => restore(args),
+    }
+}
+
+/**
+* Whether an entry of the root folder is left out of a backup by --exclude-app-data or --apps
+*/
+fn is_excluded(args: &CreateBackup, name: &str) -> bool {
+    let mut parts = name.splitn(3, '/');
+    let (folder, app_id) = (parts.next().unwrap_or_default(), parts.next());
+
+    if folder == "app-data" && args.exclude_app_data {
+        return true;
+    }
+
+    match app_id {
+        Some(app_id) if !args.apps.is_empty() && (folder == "apps" || folder == "app-data") => {
+            parts.next().is_none() && !args.apps.iter().any(|a| a == app_id)
+        }
+        _ => false,
+    }
+}
+
+fn write_archive(root_folder: &Path, archive_path: &Path, args: &CreateBackup, manifest: BackupManifest) -> Result<BackupManifest, RuntipiError> {
+    let tmp_dir = tempfile::tempdir()?;
+    let dump_path = tmp_dir.path().join("tipi.sql");
+    backup::dump_database(&dump_path).map_err(|e| RuntipiError::Docker(format!("Failed to dump the tipi database: {}", e)))?;
+
+    let mut writer = ArchiveWriter::create(archive_path)?;
+
+    let skip = |name: &str| is_excluded(args, name);
+
+    let storage_folder = env::storage_folder(root_folder);
+
+    for item in BACKUP_ITEMS {
+        let path = if is_app_data(item) {
+            storage_folder.join(item)
+        } else {
+            root_folder.join(item)
+        };
+        if !path.exists() {
+            continue;
+        }
+
+        if path.is_dir() {
+            writer.add_dir(&path, item, &skip)?;
+        } else {
+            writer.add_file(&path, item)?;
+        }
+    }
+
+    writer.add_file(&dump_path, DATABASE_DUMP_NAME)?;
+
+    Ok(writer.finish(manifest)?)
+}
+
+fn create(args: CreateBackup) -> Result<(), RuntipiError> {
+    let root_folder: PathBuf = std::env::current_dir()?;
+    let spin = spinner::new("");
+
+    spin.set_message("Checking tipi state...");
+
+    let installed_apps = system::list_installed_apps(&root_folder)?;
+    if let Some(unknown) = args.apps.iter().find(|a| !installed_apps.contains(a)) {
+        spin.fail("Invalid app selection");
+        spin.finish();
+        return Err(RuntipiError::InvalidInput(format!("App {} is not installed", unknown)));
+    }
+
+    match docker::inspect_container("tipi-db") {
+        Ok(Some(container)) if container.state == "running" => {}
+        Ok(_) => {
+            spin.fail("tipi-db is not running");
+            spin.finish();
+            return Err(RuntipiError::Docker(
+                "The database must be running to be backed up. Start tipi before creating a backup".to_string(),
+            ));
+        }
+        Err(e) => {
+            spin.fail("Unable to inspect tipi-db");
+            spin.finish();
+            return Err(RuntipiError::Docker(e.to_string()));
+        }
+    }
+
+    let tipi_version = std::fs::read_to_string(root_folder.join("VERSION"))?.trim().to_string();
+    let selected_apps = if args.apps.is_empty() { installed_apps } else { args.apps.clone() };
+
+    let destination = args.destination.clone().unwrap_or_else(|| backup::default_backup_folder(&root_folder));
+    std::fs::create_dir_all(&destination)?;
+
+    let archive_path = destination.join(backup::archive_name("runtipi-backup"));
+    let partial_path = archive_path.with_extension("partial");
+
+    spin.succeed("Tipi is ready to be backed up");
+
+    // Pause the apps so that their data is consistent while it is being archived
+    spin.set_message("Pausing apps...");
+    let containers = docker::running_project_containers(&selected_apps).map_err(|e| RuntipiError::Docker(e.to_string()))?;
+    let not_paused = backup::set_paused(&containers, true);
+    let paused = containers.into_iter().filter(|c| !not_paused.contains(c)).collect::<Vec<String>>();
+
+    if not_paused.is_empty() {
+        spin.succeed(&format!("Paused {} app containers", paused.len()));
+    } else {
+        spin.fail(&format!("Unable to pause {}. Their data may be inconsistent", not_paused.join(", ")));
+    }
+
+    spin.set_message("Creating archive... This may take a while depending on the size of your data");
+
+    let manifest = BackupManifest {
+        format_version: MANIFEST_FORMAT_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        tipi_version,
+        cli_version: env!("CARGO_PKG_VERSION").to_string(),
+        app_id: None,
+        includes_app_data: !args.exclude_app_data,
+        apps: if args.apps.is_empty() { None } else { Some(args.apps.clone()) },
+        database_dump: Some(DATABASE_DUMP_NAME.to_string()),
+        checksums: Default::default(),
+    };
+
+    let result = write_archive(&root_folder, &partial_path, &args, manifest)
+        .and_then(|manifest| Ok(std::fs::rename(&partial_path, &archive_path).map(|_| manifest)?));
+
+    let not_resumed = backup::set_paused(&paused, false);
+
+    let manifest = match result {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial_path);
+            spin.fail("Failed to create backup");
+            spin.finish();
+            return Err(e);
+        }
+    };
+
+    spin.succeed(&format!("Backup created at {}", archive_path.display()));
+
+    if !not_resumed.is_empty() {
+        spin.fail(&format!(
+            "Unable to resume {}. Run `docker unpause` on them manually",
+            not_resumed.join(", ")
+        ));
+    }
+
+    spin.finish();
+
+    output::data("archive", archive_path.display().to_string());
+    output::data("files", manifest.checksums.len());
+    output::data("tipi_version", &manifest.tipi_version);
+
+    Ok(())
+}
+
+/**
+* Paths of the root folder replaced by a restore. Backups limited to some apps only replace the folders of those apps
+*/
+fn restore_targets(content: &ArchiveContent) -> Vec<String> {
+    let mut targets = vec![];
+
+    for item in BACKUP_ITEMS {
+        match &content.manifest.apps {
+            Some(apps) if item == "apps" || item == "app-data" => targets.extend(
+                apps.iter()
+                    .map(|app_id| format!("{}/{}", item, app_id))
+                    .filter(|target| content.entries.contains(target)),
+            ),
+            _ if content.entries.iter().any(|entry| entry == item) => targets.push(item.to_string()),
+            _ => {}
+        }
+    }
+
+    targets
+}
+
+fn is_major_change(backup_version: &str, installed_version: &str) -> bool {
+    match (release::parse_version(backup_version), release::parse_version(installed_version)) {
+        (Some(backup_version), Some(installed_version)) => backup_version.major != installed_version.major,
+        _ => false,
+    }
+}
+
+fn wait_for_database(timeout: Duration) -> Result<(), RuntipiError> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        match docker::inspect_container("tipi-db") {
+            Ok(Some(container)) if container.is_healthy() => return Ok(()),
+            Ok(_) if Instant::now() < deadline => sleep(Duration::from_secs(2)),
+            Ok(_) => {
+                return Err(RuntipiError::Docker(format!(
+                    "tipi-db did not become healthy within {} seconds",
+                    timeout.as_secs()
+                )))
+            }
+            Err(e) => return Err(RuntipiError::Docker(e.to_string())),
+        }
+    }
+}
+
+/**
+* Start tipi-db alone and load the database dump of the backup into it
+*/
+fn restore_database(root_folder: &Path, dump: &Path, timeout: Duration) -> Result<(), RuntipiError> {
+    let mut args = docker::compose_file_args(root_folder);
+    args.extend(["up", "--detach", "tipi-db"].map(String::from));
+
+    let output = std::process::Command::new("docker")
+        .arg("compose")
+        .args(&args)
+        .output()
+        .map_err(|e| RuntipiError::Docker(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(RuntipiError::Compose(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+
+    wait_for_database(timeout)?;
+
+    backup::load_database_dump(dump).map_err(|e| RuntipiError::Docker(format!("Failed to load the database dump: {}", e)))?;
+
+    if let Some(password) = env::get_env_map().map_err(|e| RuntipiError::Env(e.to_string()))?.get("POSTGRES_PASSWORD") {
+        backup::set_database_password(password).map_err(|e| RuntipiError::Docker(format!("Failed to set the database password: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+/**
+* Split the restore targets between the ones that already exist and would be overwritten and the ones that would be created
+*/
+fn existing_targets(root_folder: &Path, targets: &[String]) -> (Vec<String>, Vec<String>) {
+    let storage_folder = env::storage_folder(root_folder);
+
+    targets.iter().cloned().partition(|target| {
+        let folder = if is_app_data(target) { &storage_folder } else { root_folder };
+        folder.join(target).exists()
+    })
+}
+
+fn print_dry_run(root_folder: &Path, content: &ArchiveContent, targets: &[String]) {
+    let (overwritten, created) = existing_targets(root_folder, targets);
+    let database = content.manifest.database_dump.is_some();
+
+    output::data("dry_run", true);
+    output::data("manifest", &content.manifest);
+    output::data("overwritten", &overwritten);
+    output::data("created", &created);
+    output::data("database", database);
+
+    if output::is_json() {
+        return;
+    }
+
+    println!(
+        "\nBackup of tipi {} created on {} with CLI {}\n",
+        content.manifest.tipi_version, content.manifest.created_at, content.manifest.cli_version
+    );
+
+    for target in &overwritten {
+        println!("Would overwrite {}", target);
+    }
+    for target in &created {
+        println!("Would create {}", target);
+    }
+    if database {
+        println!("Would replace the content of the tipi database");
+    }
+}
+
+fn restore(args: RestoreBackup) -> Result<(), RuntipiError> {
+    let root_folder: PathBuf = std::env::current_dir()?;
+    let timeout = Duration::from_secs(args.start.timeout);
+    let spin = spinner::new("");
+
+    spin.set_message("Verifying archive...");
+
+    let staging = if args.dry_run {
+        None
+    } else {
+        Some(tempfile::Builder::new().prefix(".restore").tempdir_in(&root_folder)?)
+    };
+
+    let result = match &staging {
+        Some(staging) => backup::extract_archive(&args.archive, staging.path()),
+        None => backup::verify_archive(&args.archive),
+    };
+
+    let content = match result {
+        Ok(content) => content,
+        Err(e) => {
+            spin.fail("Invalid backup archive");
+            spin.finish();
+            return Err(RuntipiError::InvalidInput(format!("{}: {}", args.archive.display(), e)));
+        }
+    };
+    let manifest = &content.manifest;
+
+    if let Some(app_id) = &manifest.app_id {
+        spin.fail("This archive is an app backup");
+        spin.finish();
+        return Err(RuntipiError::InvalidInput(format!(
+            "{} is a backup of the app {}. Use `app restore` instead",
+            args.archive.display(),
+            app_id
+        )));
+    }
+
+    // Same rule as updates: going to another major version requires the manual breaking update steps
+    let installed = update::installed_version(&root_folder);
+    if is_major_change(&manifest.tipi_version, &installed) {
+        spin.fail("The backup was created with another major version of tipi");
+        spin.finish();
+        return Err(RuntipiError::InvalidInput(format!(
+            "Cannot restore a backup of tipi {} on an instance running tipi {}. Restore it on an instance of the same major version",
+            manifest.tipi_version, installed
+        )));
+    }
+
+    spin.succeed(&format!("Archive verified ({} files)", manifest.checksums.len()));
+
+    let targets = restore_targets(&content);
+
+    let staging = match staging {
+        Some(staging) => staging,
+        None => {
+            spin.finish();
+            print_dry_run(&root_folder, &content, &targets);
+            return Ok(());
+        }
+    };
+    spin.finish();
+
+    if root_folder.join("docker-compose.yml").exists() {
+        stop::run()?;
+    }
+
+    let spin = spinner::new("");
+    spin.set_message("Restoring files...");
+
+    // app-data goes to the storage folder of the restored .env, once it has been generated
+    let (data_targets, root_targets): (Vec<String>, Vec<String>) = targets.iter().cloned().partition(|target| is_app_data(target));
+
+    if let Err(e) = backup::replace_paths(&root_folder, staging.path(), &root_targets) {
+        spin.fail("Failed to restore files");
+        spin.finish();
+        return Err(e.into());
+    }
+
+    spin.succeed(&format!("Restored {}", root_targets.join(", ")));
+
+    spin.set_message("Preparing system files...");
+
+    if let Err(e) = system::copy_system_files() {
+        spin.fail("Failed to copy system files");
+        spin.finish();
+        return Err(e.into());
+    }
+
+    if let Err(e) = env::generate_env_file(args.start.env_file.clone()) {
+        spin.fail("Failed to generate .env file");
+        spin.finish();
+        return Err(RuntipiError::Env(e.to_string()));
+    }
+
+    if !data_targets.is_empty() {
+        let storage_folder = env::storage_folder(&root_folder);
+
+        if let Err(e) = backup::replace_paths(&storage_folder, staging.path(), &data_targets) {
+            spin.fail("Failed to restore app data");
+            spin.finish();
+            return Err(e.into());
+        }
+    }
+
+    if !args.start.no_permissions {
+        if let Err(e) = system::ensure_file_permissions() {
+            spin.fail("Failed to ensure file permissions");
+            spin.finish();
+            return Err(RuntipiError::Permissions(e.to_string()));
+        }
+    }
+
+    spin.succeed("System files and permissions ok");
+
+    if let Some(dump) = &manifest.database_dump {
+        spin.set_message("Restoring database...");
+
+        if let Err(e) = restore_database(&root_folder, &staging.path().join(dump), timeout) {
+            spin.fail("Failed to restore the database");
+            spin.finish();
+            return Err(e);
+        }
+
+        spin.succeed("Database restored");
+    }
+
+    spin.finish();
+
+    output::data("archive", args.archive.display().to_string());
+    output::data("restored", &targets);
+    output::data("tipi_version", &manifest.tipi_version);
+
+    drop(staging);
+
+    start::run(args.start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::backup::MANIFEST_NAME;
+
+    fn create_args(exclude_app_data: bool, apps: &[&str]) -> CreateBackup {
+        CreateBackup {
+            destination: None,
+            exclude_app_data,
+            apps: apps.iter().map(|app| app.to_string()).collect(),
+        }
+    }
+
+    fn content(apps: Option<&[&str]>, entries: &[&str]) -> ArchiveContent {
+        ArchiveContent {
+            manifest: BackupManifest {
+                format_version: MANIFEST_FORMAT_VERSION,
+                created_at: String::new(),
+                tipi_version: "v3.0.0".to_string(),
+                cli_version: "v3.0.0".to_string(),
+                app_id: None,
+                includes_app_data: true,
+                apps: apps.map(|apps| apps.iter().map(|app| app.to_string()).collect()),
+                database_dump: Some(DATABASE_DUMP_NAME.to_string()),
+                checksums: Default::default(),
+            },
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn excludes_app_data() {
+        let args = create_args(true, &[]);
+
+        assert!(is_excluded(&args, "app-data"));
+        assert!(is_excluded(&args, "app-data/nextcloud/data"));
+        assert!(!is_excluded(&args, "apps/nextcloud"));
+        assert!(!is_excluded(&args, "state/settings.json"));
+        assert!(!is_excluded(&create_args(false, &[]), "app-data/nextcloud"));
+    }
+
+    #[test]
+    fn excludes_apps_not_selected() {
+        let args = create_args(false, &["nextcloud"]);
+
+        assert!(!is_excluded(&args, "apps"));
+        assert!(!is_excluded(&args, "apps/nextcloud"));
+        assert!(!is_excluded(&args, "app-data/nextcloud"));
+        assert!(is_excluded(&args, "apps/jellyfin"));
+        assert!(is_excluded(&args, "app-data/jellyfin"));
+        // Only the app folders are filtered, files below them are reached through their folder
+        assert!(!is_excluded(&args, "apps/jellyfin/docker-compose.yml"));
+        assert!(!is_excluded(&args, "user-config/jellyfin"));
+        assert!(!is_excluded(&args, "state/settings.json"));
+    }
+
+    #[test]
+    fn restores_every_item_of_a_full_backup() {
+        let content = content(
+            None,
+            &[".env", "state", "state/settings.json", "apps", "apps/nextcloud", "app-data", "traefik"],
+        );
+
+        assert_eq!(restore_targets(&content), [".env", "state", "app-data", "apps", "traefik"]);
+    }
+
+    #[test]
+    fn restores_only_the_apps_of_a_partial_backup() {
+        let content = content(
+            Some(&["nextcloud", "jellyfin"]),
+            &[
+                ".env",
+                "state",
+                "apps",
+                "apps/nextcloud",
+                "apps/jellyfin",
+                "app-data",
+                "app-data/nextcloud",
+                MANIFEST_NAME,
+            ],
+        );
+
+        assert_eq!(
+            restore_targets(&content),
+            [".env", "state", "app-data/nextcloud", "apps/nextcloud", "apps/jellyfin"]
+        );
+    }
+
+    #[test]
+    fn lists_existing_targets_in_the_storage_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join(".env"), format!("STORAGE_PATH={}\n", storage.path().display())).unwrap();
+        std::fs::create_dir_all(root.path().join("apps/nextcloud")).unwrap();
+        std::fs::create_dir_all(root.path().join("app-data/jellyfin")).unwrap();
+        std::fs::create_dir_all(storage.path().join("app-data/nextcloud")).unwrap();
+
+        let targets = [".env", "state", "apps/nextcloud", "app-data/nextcloud", "app-data/jellyfin"].map(String::from);
+        let (overwritten, created) = existing_targets(root.path(), &targets);
+
+        assert_eq!(overwritten, [".env", "apps/nextcloud", "app-data/nextcloud"]);
+        assert_eq!(created, ["state", "app-data/jellyfin"]);
+    }
+
+    #[test]
+    fn compares_major_versions() {
+        assert!(!is_major_change("v3.0.0", "v3.7.1"));
+        assert!(!is_major_change("3.7.1\n", "v3.0.0"));
+        assert!(is_major_change("v2.9.0", "v3.0.0"));
+        assert!(is_major_change("v4.0.0-beta.1", "v3.7.1"));
+        assert!(!is_major_change("nightly", "v3.7.1"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::env::current_dir;
+
+use colored::Colorize;
+use prettytable::{format, row, Table};
+use serde_json::Value;
+
+use crate::args::{ConfigCommand, ConfigKey, ConfigSubcommand, SetConfig};
+use crate::components::output;
+use crate::error::RuntipiError;
+use crate::utils::settings::{self, Severity, SETTINGS};
+
+// Keep tokens out of screenshots and terminal logs, `config get` still prints them
+const SECRET_MASK: &str = "********";
+
+pub fn run(args: ConfigCommand) -> Result<(), RuntipiError> {
+    match args.subcommand {
+        ConfigSubcommand::Get(args) => get(args),
+        ConfigSubcommand::Set(args) => set(args),
+        ConfigSubcommand::Unset(args) => unset(args),
+        ConfigSubcommand::List => list(),
+        ConfigSubcommand::Validate => validate(),
+    }
+}
+
+fn read_settings() -> Result<serde_json::Map<String, Value>, RuntipiError> {
+    settings::read(&current_dir()?).map_err(|e| RuntipiError::InvalidInput(e.to_string()))
+}
+
+/**
+* Value as shown to the user, strings without their quotes
+*/
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn unknown_key_error(key: &str) -> RuntipiError {
+    let keys = SETTINGS.iter().map(|definition| definition.key).collect::<Vec<&str>>();
+    RuntipiError::InvalidInput(format!("Unknown setting {}. Available settings: {}", key, keys.join(", ")))
+}
+
+fn get(args: ConfigKey) -> Result<(), RuntipiError> {
+    let settings = read_settings()?;
+    let definition = settings::definition(&args.key);
+
+    match (settings::get(&settings, &args.key), definition) {
+        (Some(value), _) => {
+            output::data("key", &args.key);
+            output::data("value", value);
+            output::info(&display_value(value));
+        }
+        (None, Some(definition)) => {
+            output::data("key", &args.key);
+            output::data("value", Value::Null);
+            output::data("default", definition.default);
+            match definition.default {
+                Some(default) => output::info(&format!("{} is not set (default: {})", args.key, default)),
+                None => output::info(&format!("{} is not set", args.key)),
+            }
+        }
+        (None, None) => return Err(unknown_key_error(&args.key)),
+    }
+
+    Ok(())
+}
+
+fn set(args: SetConfig) -> Result<(), RuntipiError> {
+    let root_folder = current_dir()?;
+    let definition = settings::definition(&args.key).ok_or_else(|| unknown_key_error(&args.key))?;
+
+    let value = definition
+        .kind
+        .parse(&args.value)
+        .map_err(|e| RuntipiError::InvalidInput(format!("Invalid value for {}: {}", args.key, e)))?;
+
+    let mut settings = read_settings()?;
+    settings::set(&mut settings, &args.key, value.clone());
+    settings::write(&root_folder, &settings)?;
+
+    output::data("key", &args.key);
+    output::data("value", &value);
+    output::success(&format!("{} set to {}", args.key, display_value(&value)));
+    output::info("Run `runtipi-cli restart` to apply the change");
+
+    Ok(())
+}
+
+fn unset(args: ConfigKey) -> Result<(), RuntipiError> {
+    let root_folder = current_dir()?;
+    let mut settings = read_settings()?;
+
+    if !settings::unset(&mut settings, &args.key) {
+        if settings::definition(&args.key).is_none() {
+            return Err(unknown_key_error(&args.key));
+        }
+
+        output::info(&format!("{} is not set", args.key));
+        return Ok(());
+    }
+
+    settings::write(&root_folder, &settings)?;
+
+    output::data("key", &args.key);
+    output::success(&format!("{} unset", args.key));
+    output::info("Run `runtipi-cli restart` to apply the change");
+
+    Ok(())
+}
+
+fn list() -> Result<(), RuntipiError> {
+    let settings = read_settings()?;
+    let unknown_keys = settings::unknown_keys(&settings);
+
+    if output::is_json() {
+        let mut redacted = Value::Object(settings.clone());
+        settings::redact_secrets(&mut redacted, SECRET_MASK);
+        output::data("settings", &redacted);
+        output::data("unknown_keys", &unknown_keys);
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(row!["Setting", "Value", "Default", "Description"]);
+
+    for definition in SETTINGS {
+        let value = match settings::get(&settings, definition.key) {
+            Some(_) if settings::is_secret(definition.key) => SECRET_MASK.green(),
+            Some(value) => display_value(value).green(),
+            None => "-".dimmed(),
+        };
+        table.add_row(row![definition.key, value, definition.default.unwrap_or("-"), definition.description]);
+    }
+
+    for key in &unknown_keys {
+        let value = match settings::get(&settings, key) {
+            Some(_) if settings::is_secret(key) => SECRET_MASK.to_string(),
+            Some(value) => display_value(value),
+            None => String::new(),
+        };
+        table.add_row(row![key, value, "-", "Not used by the CLI".dimmed()]);
+    }
+
+    table.printstd();
+
+    Ok(())
+}
+
+fn validate() -> Result<(), RuntipiError> {
+    let issues = settings::check_file(&current_dir()?)?;
+
+    output::data("issues", &issues);
+
+    for issue in issues.iter().filter(|issue| issue.severity == Severity::Warning) {
+        output::info(&format!("⚠️ {}", issue));
+    }
+
+    if let Some(e) = settings::issues_error(&issues) {
+        return Err(RuntipiError::InvalidInput(e.to_string()));
+    }
+
+    output::success("settings.json is valid");
+
+    Ok(())
+}
@@ -1,30 +1,141 @@
 use colored::Colorize;
 
 use prettytable::{format, row, Table};
-use serde_json::{to_string_pretty, Value};
+use serde::Serialize;
+use serde_json::{to_string_pretty, Map, Value};
+
+use crate::components::output;
+use crate::error::RuntipiError;
+use crate::utils::{env::env_string_to_map, settings, system::get_architecture};
+
+// (key, redacted, expected to be set)
+const ENV_VARIABLES: [(&str, bool, bool); 25] = [
+    ("POSTGRES_PASSWORD", true, true),
+    ("REDIS_PASSWORD", true, true),
+    ("APPS_REPO_ID", false, true),
+    ("APPS_REPO_URL", false, true),
+    ("TIPI_VERSION", false, true),
+    ("INTERNAL_IP", false, true),
+    ("ARCHITECTURE", false, true),
+    ("JWT_SECRET", true, true),
+    ("ROOT_FOLDER_HOST", false, true),
+    ("STORAGE_PATH", false, true),
+    ("NGINX_PORT", false, true),
+    ("NGINX_PORT_SSL", false, true),
+    ("DOMAIN", true, false),
+    ("POSTGRES_HOST", false, false),
+    ("POSTGRES_DBNAME", false, false),
+    ("POSTGRES_USERNAME", false, false),
+    ("POSTGRES_PORT", false, true),
+    ("REDIS_HOST", false, false),
+    ("DEMO_MODE", false, false),
+    ("LOCAL_DOMAIN", false, false),
+    ("DNS_IP", false, false),
+    ("GUEST_DASHBOARD", false, false),
+    ("ALLOW_ERROR_MONITORING", false, false),
+    ("ALLOW_AUTO_THEMES", false, false),
+    ("PERSIST_TRAEFIK_CONFIG", false, false),
+];
+
+#[derive(Debug, Serialize)]
+struct SystemInfo {
+    os: String,
+    os_version: String,
+    memory_gb: f64,
+    architecture: String,
+}
 
-use crate::utils::{env::env_string_to_map, system::get_architecture};
+#[derive(Debug, Serialize)]
+struct ContainerStatus {
+    name: String,
+    status: String,
+}
 
-pub fn run() {
-    println!("⚠️ Make sure you have started tipi before running this command\n");
-    // Gather system information
-    let os = std::env::consts::OS;
-    let version = sys_info::os_release().unwrap_or_else(|_| "Unknown".to_string());
-    let mem = sys_info::mem_info().map(|mi| mi.total).unwrap_or(0);
-    let arch = get_architecture().unwrap_or("Unknown".to_string());
+fn get_containers() -> Option<Vec<ContainerStatus>> {
+    let output = std::process::Command::new("docker")
+        .arg("ps")
+        .arg("-a")
+        .arg("--filter")
+        .arg("name=tipi-")
+        .arg("--format")
+        .arg("{{.Names}} {{.Status}}")
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let containers = String::from_utf8_lossy(&output.stdout)
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let (name, status) = s.split_once(' ').unwrap_or((s, ""));
+            ContainerStatus {
+                name: name.to_string(),
+                status: status.to_string(),
+            }
+        })
+        .collect();
+
+    Some(containers)
+}
+
+pub fn run() -> Result<(), RuntipiError> {
     let current_dir = std::env::current_dir().unwrap_or_default();
 
+    // Gather system information
+    let system_info = SystemInfo {
+        os: std::env::consts::OS.to_string(),
+        os_version: sys_info::os_release().unwrap_or_else(|_| "Unknown".to_string()),
+        memory_gb: sys_info::mem_info().map(|mi| mi.total).unwrap_or(0) as f64 / 1024.0 / 1024.0,
+        architecture: get_architecture().unwrap_or("Unknown".to_string()),
+    };
+
+    // Does the file user_config/tipi-config.yml exist?
+    let custom_config = std::path::Path::new("user-config/tipi-config.yml").exists();
+
+    let settings_file_path = current_dir.join("state").join("settings.json");
+    let json_string = std::fs::read_to_string(&settings_file_path).unwrap_or_default();
+    let mut settings: Value = serde_json::from_str(&json_string).unwrap_or_default();
+    // The output of debug ends up in bug reports
+    settings::redact_secrets(&mut settings, "<redacted>");
+
+    let env_file_path = current_dir.join(".env");
+    let env_file = std::fs::read_to_string(&env_file_path).unwrap_or_default();
+    let env_map = env_string_to_map(env_file.as_str());
+
+    let containers = get_containers();
+
+    if output::is_json() {
+        let mut env = Map::new();
+        for (key, redacted, _) in ENV_VARIABLES {
+            let value = match env_map.get(key) {
+                Some(_) if redacted => Value::String("<redacted>".to_string()),
+                Some(value) => Value::String(value.clone()),
+                None => Value::Null,
+            };
+            env.insert(key.to_string(), value);
+        }
+
+        output::data("system", &system_info);
+        output::data("custom_tipi_docker_config", custom_config);
+        output::data("settings", &settings);
+        output::data("env", &env);
+        output::data("containers", &containers);
+        return Ok(());
+    }
+
+    println!("⚠️ Make sure you have started tipi before running this command\n");
+
     // Create a table and add rows with system information
     println!("--- {} ---", "System information".blue());
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_BOX_CHARS);
-    table.add_row(row!["OS", os]);
-    table.add_row(row!["OS Version", version]);
-    table.add_row(row!["Memory (GB)", format!("{:.2}", mem as f64 / 1024.0 / 1024.0)]);
-    table.add_row(row!["Architecture", arch]);
-
-    // Does the file user_config/tipi-config.yml exist?
-    let config_file = std::path::Path::new("user-config/tipi-config.yml");
+    table.add_row(row!["OS", system_info.os]);
+    table.add_row(row!["OS Version", system_info.os_version]);
+    table.add_row(row!["Memory (GB)", format!("{:.2}", system_info.memory_gb)]);
+    table.add_row(row!["Architecture", system_info.architecture]);
 
     // Print the table
     table.printstd();
@@ -35,19 +146,15 @@ pub fn run() {
     table.set_format(*format::consts::FORMAT_BOX_CHARS);
     table.add_row(row![
         "Custom tipi docker config",
-        if config_file.exists() { "Yes".yellow() } else { "No".bright_white() }
+        if custom_config { "Yes".yellow() } else { "No".bright_white() }
     ]);
 
     table.printstd();
 
     println!("\n--- {} ---", "Settings.json".blue());
-    let settings_file_path = current_dir.join("state").join("settings.json");
-
-    let json_string = std::fs::read_to_string(&settings_file_path).unwrap_or_default();
-    let parsed_json: Value = serde_json::from_str(&json_string).unwrap_or_default();
 
     // Pretty print the JSON
-    let pretty_json = to_string_pretty(&parsed_json).unwrap_or_else(|_| {
+    let pretty_json = to_string_pretty(&settings).unwrap_or_else(|_| {
         eprintln!("Failed to generate pretty JSON.");
         String::new()
     });
@@ -58,100 +165,38 @@ pub fn run() {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_BOX_CHARS);
 
-    let env_file_path = current_dir.join(".env");
-    let env_file = std::fs::read_to_string(&env_file_path).unwrap_or_default();
-    let env_map = env_string_to_map(env_file.as_str());
-
-    let pg_password = env_map
-        .get("POSTGRES_PASSWORD")
-        .map(|_| "<redacted>".to_string())
-        .unwrap_or("Not set".red().to_string());
-    let redis_password = env_map
-        .get("REDIS_PASSWORD")
-        .map(|_| "<redacted>".to_string())
-        .unwrap_or("Not set".red().to_string());
-    let jwt_secret = env_map
-        .get("JWT_SECRET")
-        .map(|_| "<redacted>".to_string())
-        .unwrap_or("Not set".red().to_string());
-    let domain = env_map.get("DOMAIN").map(|_| "<redacted>").unwrap_or("Not set");
-
-    table.add_row(row!["POSTGRES_PASSWORD", pg_password]);
-    table.add_row(row!["REDIS_PASSWORD", redis_password]);
-    table.add_row(row!["APPS_REPO_ID", env_map.get("APPS_REPO_ID").unwrap_or(&"Not set".red().to_string())]);
-    table.add_row(row![
-        "APPS_REPO_URL",
-        env_map.get("APPS_REPO_URL").unwrap_or(&"Not set".red().to_string())
-    ]);
-    table.add_row(row!["TIPI_VERSION", env_map.get("TIPI_VERSION").unwrap_or(&"Not set".red().to_string())]);
-    table.add_row(row!["INTERNAL_IP", env_map.get("INTERNAL_IP").unwrap_or(&"Not set".red().to_string())]);
-    table.add_row(row!["ARCHITECTURE", env_map.get("ARCHITECTURE").unwrap_or(&"Not set".red().to_string())]);
-    table.add_row(row!["JWT_SECRET", jwt_secret]);
-    table.add_row(row![
-        "ROOT_FOLDER_HOST",
-        env_map.get("ROOT_FOLDER_HOST").unwrap_or(&"Not set".red().to_string())
-    ]);
-    table.add_row(row!["STORAGE_PATH", env_map.get("STORAGE_PATH").unwrap_or(&"Not set".red().to_string())]);
-    table.add_row(row!["NGINX_PORT", env_map.get("NGINX_PORT").unwrap_or(&"Not set".red().to_string())]);
-    table.add_row(row![
-        "NGINX_PORT_SSL",
-        env_map.get("NGINX_PORT_SSL").unwrap_or(&"Not set".red().to_string())
-    ]);
-    table.add_row(row!["DOMAIN", domain]);
-    table.add_row(row!["POSTGRES_HOST", env_map.get("POSTGRES_HOST").unwrap_or(&"Not set".to_string())]);
-    table.add_row(row!["POSTGRES_DBNAME", env_map.get("POSTGRES_DBNAME").unwrap_or(&"Not set".to_string())]);
-    table.add_row(row![
-        "POSTGRES_USERNAME",
-        env_map.get("POSTGRES_USERNAME").unwrap_or(&"Not set".to_string())
-    ]);
-    table.add_row(row![
-        "POSTGRES_PORT",
-        env_map.get("POSTGRES_PORT").unwrap_or(&"Not set".red().to_string())
-    ]);
-    table.add_row(row!["REDIS_HOST", env_map.get("REDIS_HOST").unwrap_or(&"Not set".to_string())]);
-    table.add_row(row!["DEMO_MODE", env_map.get("DEMO_MODE").unwrap_or(&"Not set".to_string())]);
-    table.add_row(row!["LOCAL_DOMAIN", env_map.get("LOCAL_DOMAIN").unwrap_or(&"Not set".to_string())]);
+    for (key, redacted, expected) in ENV_VARIABLES {
+        let value = match env_map.get(key) {
+            Some(_) if redacted => "<redacted>".normal(),
+            Some(value) => value.normal(),
+            None if expected => "Not set".red(),
+            None => "Not set".normal(),
+        };
+        table.add_row(row![key, value]);
+    }
 
     table.printstd();
 
     println!("\n--- {} ---", "Docker containers".blue());
     table = Table::new();
     table.set_format(*format::consts::FORMAT_BOX_CHARS);
-    let containers = std::process::Command::new("docker")
-        .arg("ps")
-        .arg("-a")
-        .arg("--filter")
-        .arg("name=tipi-")
-        .arg("--format")
-        .arg("{{.Names}} {{.Status}}")
-        .output()
-        .map_err(|e| e.to_string());
 
     match containers {
-        Ok(output) => {
-            if output.status.success() {
-                let containers = String::from_utf8_lossy(&output.stdout);
-                let containers = containers
-                    .split("\n")
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.to_string())
-                    .collect::<Vec<String>>();
-                for container in containers {
-                    let array = container.split(" ").collect::<Vec<&str>>();
-
-                    let status = if array[1].contains("Up") { array[1].green() } else { array[1].red() };
-
-                    table.add_row(row![array[0], status]);
-                }
-            } else {
-                table.add_row(row!["No containers found"]);
+        Some(containers) if !containers.is_empty() => {
+            for container in containers {
+                let state = container.status.split(' ').next().unwrap_or_default();
+                let status = if state.contains("Up") { state.green() } else { state.red() };
+
+                table.add_row(row![container.name, status]);
             }
         }
-        Err(_) => {
+        _ => {
             table.add_row(row!["No containers found"]);
         }
     };
 
     table.printstd();
     println!("^ If a container is not 'Up', you can run the command `docker logs <container_name>` to see the logs of that container.");
+
+    Ok(())
 }
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::args::ExportImagesArgs;
+use crate::components::{output, spinner};
+use crate::error::RuntipiError;
+use crate::utils::constants::{DOCKER_COMPOSE_YML, VERSION};
+use crate::utils::env::env_string_to_map;
+use crate::utils::{docker, dotenv, release};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedImage {
+    service: String,
+    image: String,
+    id: String,
+    repo_digests: Vec<String>,
+}
+
+/// Written next to the archive so that the images can be checked on the isolated host
+#[derive(Debug, Serialize, Deserialize)]
+struct ImagesManifest {
+    created_at: String,
+    tipi_version: String,
+    archive: String,
+    sha256: String,
+    images: Vec<ExportedImage>,
+}
+
+/**
+* Image of every service of the embedded docker-compose.yml, overridden by user-config/tipi-compose.yml
+*/
+fn service_images(root_folder: &std::path::Path) -> Vec<(String, String)> {
+    let mut services = docker::compose_service_images(DOCKER_COMPOSE_YML);
+
+    let user_compose = std::fs::read_to_string(root_folder.join("user-config").join("tipi-compose.yml")).unwrap_or_default();
+    for (service, image) in docker::compose_service_images(&user_compose) {
+        match services.iter_mut().find(|(name, _)| *name == service) {
+            Some(existing) => existing.1 = image,
+            None => services.push((service, image)),
+        }
+    }
+
+    services
+}
+
+/**
+* Check an archive created by export-images against the checksum of its manifest
+*/
+pub fn verify_archive(archive: &Path, manifest_path: &Path) -> Result<(), String> {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let content = std::fs::read_to_string(manifest_path).map_err(|e| format!("Unable to read {}: {}", manifest_path.display(), e))?;
+    let manifest: ImagesManifest = serde_json::from_str(&content).map_err(|e| format!("Invalid manifest {}: {}", manifest_path.display(), e))?;
+
+    if manifest.archive != name {
+        return Err(format!(
+            "{} is the manifest of {}, not of {}",
+            manifest_path.display(),
+            manifest.archive,
+            name
+        ));
+    }
+
+    let actual = release::sha256_file(archive).map_err(|e| format!("Unable to read {}: {}", name, e))?;
+
+    if actual != manifest.sha256 {
+        return Err(format!(
+            "Checksum mismatch for {}: expected {} but got {}. The archive may be corrupted or tampered with",
+            name, manifest.sha256, actual
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn run(args: ExportImagesArgs) -> Result<(), RuntipiError> {
+    let root_folder: PathBuf = std::env::current_dir()?;
+    let spin = spinner::new("");
+
+    let env_file = std::fs::read_to_string(root_folder.join(".env")).unwrap_or_default();
+    let mut env_map: HashMap<String, String> = env_string_to_map(&env_file);
+    let tipi_version = env_map
+        .entry("TIPI_VERSION".to_string())
+        .or_insert_with(|| release::image_tag(VERSION))
+        .clone();
+
+    let services = service_images(&root_folder)
+        .into_iter()
+        .map(|(service, image)| (service, dotenv::interpolate(&image, &env_map)))
+        .collect::<Vec<(String, String)>>();
+
+    let mut exported = vec![];
+    let mut missing = vec![];
+
+    for (service, image) in services {
+        if args.pull {
+            spin.set_message(&format!("Pulling {}...", image));
+
+            if let Err(e) = docker::pull_image(&image) {
+                spin.fail(&format!("Failed to pull {}", image));
+                spin.finish();
+                return Err(RuntipiError::Docker(e.to_string()));
+            }
+        }
+
+        match docker::inspect_image(&image) {
+            Ok(Some((id, repo_digests))) => exported.push(ExportedImage {
+                service,
+                image,
+                id,
+                repo_digests,
+            }),
+            Ok(None) => missing.push(image),
+            Err(e) => {
+                spin.fail("Unable to inspect images");
+                spin.finish();
+                return Err(RuntipiError::Docker(e.to_string()));
+            }
+        }
+    }
+
+    if !missing.is_empty() {
+        spin.fail("Some images are not available locally");
+        spin.finish();
+        return Err(RuntipiError::Docker(format!(
+            "Missing images: {}. Run the command again with --pull to download them",
+            missing.join(", ")
+        )));
+    }
+
+    spin.succeed(&format!("Found {} images", exported.len()));
+
+    let destination = args.destination.unwrap_or_else(|| root_folder.clone());
+    std::fs::create_dir_all(&destination)?;
+
+    let name = format!("runtipi-images-{}", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
+    let archive_path = destination.join(format!("{}.tar", name));
+    let manifest_path = destination.join(format!("{}.json", name));
+    let partial_path = archive_path.with_extension("partial");
+
+    spin.set_message("Saving images... This may take a while");
+
+    let mut images = exported.iter().map(|e| e.image.clone()).collect::<Vec<String>>();
+    images.dedup();
+
+    let result = docker::save_images(&images, &partial_path)
+        .and_then(|_| std::fs::rename(&partial_path, &archive_path))
+        .and_then(|_| release::sha256_file(&archive_path));
+
+    let sha256 = match result {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial_path);
+            spin.fail("Failed to save images");
+            spin.finish();
+            return Err(RuntipiError::Docker(e.to_string()));
+        }
+    };
+
+    let manifest = ImagesManifest {
+        created_at: chrono::Utc::now().to_rfc3339(),
+        tipi_version,
+        archive: archive_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        sha256,
+        images: exported,
+    };
+    let content = serde_json::to_string_pretty(&manifest).map_err(|e| RuntipiError::Other(e.to_string()))?;
+    std::fs::write(&manifest_path, content)?;
+
+    spin.succeed(&format!("Images saved to {}", archive_path.display()));
+    spin.finish();
+
+    output::data("archive", archive_path.display().to_string());
+    output::data("manifest", manifest_path.display().to_string());
+    output::data("images", &manifest.images);
+
+    Ok(())
+}
@@ -1,6 +1,10 @@
 pub mod app;
+pub mod backup;
+pub mod config;
 pub mod debug;
+pub mod export_images;
+pub mod reset_password;
 pub mod start;
+pub mod status;
 pub mod stop;
 pub mod update;
-pub mod reset_password;
\ No newline at end of file
@@ -1,25 +1,24 @@
-use colored::Colorize;
 use std::env;
 use std::{fs::File, path::PathBuf};
 
-pub fn run() {
-    let root_folder: PathBuf = env::current_dir().expect("Unable to get current directory");
+use crate::components::output;
+use crate::error::RuntipiError;
+
+pub fn run() -> Result<(), RuntipiError> {
+    let root_folder: PathBuf = env::current_dir()?;
     let reset_password_request = File::create(root_folder.join("state").join("password-change-request"));
 
     match reset_password_request {
         Ok(_) => {
-            println!(
-                "{} Password reset request created. Head back to the dashboard to set a new password.",
-                "✓".green()
-            )
+            output::success("Password reset request created. Head back to the dashboard to set a new password.");
+            Ok(())
         }
-        Err(_) => {
-            println!(
-                "{} Unable to create password reset request. You can manually create an empty file at {} to initiate a password reset.",
-                "✗".red(),
-                root_folder.join("state").join("password-change-request").to_str().unwrap()
-            );
-            return;
+        Err(e) => {
+            output::failure(&format!(
+                "Unable to create password reset request. You can manually create an empty file at {} to initiate a password reset.",
+                root_folder.join("state").join("password-change-request").display()
+            ));
+            Err(e.into())
         }
     }
 }
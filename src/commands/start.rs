@@ -1,21 +1,153 @@
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::Client;
 
 use crate::args::StartArgs;
+use crate::commands::update;
 use crate::components::console_box::ConsoleBox;
-use crate::components::spinner;
-use crate::utils::{env, system};
+use crate::components::{output, spinner};
+use crate::error::RuntipiError;
+use crate::utils::constants::CORE_CONTAINERS;
+use crate::utils::settings::{self, Severity};
+use crate::utils::{docker, env, system};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const LOG_LINES: usize = 20;
+
+fn print_logs(container_name: &str) {
+    match docker::container_logs(container_name, LOG_LINES) {
+        Ok(logs) => output::info(&format!("\nLast {} log lines of {}:\n{}", LOG_LINES, container_name, logs.trim_end())),
+        Err(e) => output::info(&format!("\nUnable to get logs of {}: {}", container_name, e)),
+    }
+}
 
-pub fn run(args: StartArgs) {
+/**
+* Wait for every core container to be running and healthy, then for the dashboard to answer through the reverse proxy
+*/
+fn wait_until_healthy(spin: &spinner::CustomSpinner, dashboard_url: &str, timeout: Duration) -> Result<(), RuntipiError> {
+    let deadline = Instant::now() + timeout;
+    let mut pending: Vec<(&str, String)> = CORE_CONTAINERS
+        .iter()
+        .filter(|name| docker::is_compose_container(name))
+        .map(|name| (*name, "starting".to_string()))
+        .collect();
+
+    loop {
+        let mut crashed = None;
+
+        pending.retain_mut(|(name, state)| match docker::inspect_container(name) {
+            Ok(Some(container)) if container.is_healthy() => {
+                spin.succeed(&format!("{} is healthy", name));
+                false
+            }
+            Ok(Some(container)) => {
+                if container.state == "exited" || container.state == "dead" {
+                    crashed = Some(name.to_string());
+                }
+                *state = container.health.unwrap_or(container.state);
+                true
+            }
+            Ok(None) => {
+                *state = "missing".to_string();
+                true
+            }
+            Err(e) => {
+                *state = e.to_string();
+                true
+            }
+        });
+
+        if pending.is_empty() {
+            break;
+        }
+
+        if crashed.is_some() || Instant::now() >= deadline {
+            let reason = match crashed {
+                Some(name) => format!("{} stopped unexpectedly", name),
+                None => format!("Services did not become healthy within {} seconds", timeout.as_secs()),
+            };
+            spin.fail(&reason);
+            spin.finish();
+
+            for (name, _) in &pending {
+                print_logs(name);
+            }
+
+            let details = pending
+                .iter()
+                .map(|(name, state)| format!("{} ({})", name, state))
+                .collect::<Vec<String>>();
+            return Err(RuntipiError::Degraded(format!("Unhealthy services: {}", details.join(", "))));
+        }
+
+        let waiting_for = pending
+            .iter()
+            .map(|(name, state)| format!("{} ({})", name, state))
+            .collect::<Vec<String>>();
+        spin.set_message(&format!("Waiting for {}...", waiting_for.join(", ")));
+        sleep(POLL_INTERVAL);
+    }
+
+    // The dashboard is only reachable once traefik has picked up its labels. Until then traefik answers 404
+    spin.set_message("Waiting for the dashboard to be reachable...");
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| RuntipiError::Network(e.to_string()))?;
+    let probe_url = dashboard_url.replace("://0.0.0.0:", "://localhost:");
+
+    loop {
+        let last_error = match client.get(&probe_url).send() {
+            Ok(response) if response.status().is_success() || response.status().is_redirection() => break,
+            Ok(response) => format!("dashboard answered with {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if Instant::now() >= deadline {
+            spin.fail("The dashboard is not reachable");
+            spin.finish();
+            print_logs("tipi-dashboard");
+            print_logs("tipi-reverse-proxy");
+            return Err(RuntipiError::Degraded(format!("{} is not reachable: {}", probe_url, last_error)));
+        }
+
+        sleep(POLL_INTERVAL);
+    }
+
+    spin.succeed("Dashboard is reachable");
+
+    Ok(())
+}
+
+/**
+* Url of the dashboard built from the INTERNAL_IP and NGINX_PORT of the .env file
+*/
+pub fn dashboard_url(env_map: &HashMap<String, String>) -> Result<String, RuntipiError> {
+    let value = |key: &str| {
+        env_map
+            .get(key)
+            .ok_or_else(|| RuntipiError::Env(format!("{} is missing from the .env file", key)))
+    };
+
+    Ok(format!("http://{}:{}", value("INTERNAL_IP")?, value("NGINX_PORT")?))
+}
+
+pub fn run(args: StartArgs) -> Result<(), RuntipiError> {
     let spin = spinner::new("");
+    let timeout = Duration::from_secs(args.timeout);
 
     // User permissions
     spin.set_message("Checking user permissions");
 
     if let Err(e) = system::ensure_docker() {
-        spin.fail(e.to_string().as_str());
+        spin.fail("Docker is not available");
         spin.finish();
-        return;
+        return Err(RuntipiError::Docker(e.to_string()));
     }
 
     spin.succeed("User permissions are ok");
@@ -26,8 +158,7 @@ pub fn run(args: StartArgs) {
     if let Err(e) = system::copy_system_files() {
         spin.fail("Failed to copy system files");
         spin.finish();
-        println!("\nError: {}", e);
-        return;
+        return Err(e.into());
     }
     spin.succeed("Copied system files");
 
@@ -37,69 +168,72 @@ pub fn run(args: StartArgs) {
     if let Err(e) = env::generate_env_file(args.env_file) {
         spin.fail("Failed to generate .env file");
         spin.finish();
-        println!("\nError: {}", e);
-        return;
+        return Err(RuntipiError::Env(e.to_string()));
     }
-    let env_map = env::get_env_map();
+    let env_map = match env::get_env_map() {
+        Ok(env_map) => env_map,
+        Err(e) => {
+            spin.fail("Failed to read .env file");
+            spin.finish();
+            return Err(RuntipiError::Env(e.to_string()));
+        }
+    };
 
     spin.succeed("Generated .env file");
 
+    // Errors stop the generation of the .env file, warnings are only shown
+    if let Ok(issues) = settings::check_file(&current_dir()?) {
+        for issue in issues.iter().filter(|issue| issue.severity == Severity::Warning) {
+            spin.suspend(|| output::info(&format!("⚠️ settings.json: {}", issue)));
+        }
+    }
+
     spin.set_message("Ensuring file permissions... This may take a while depending on how many files there are to fix");
 
     if !args.no_permissions {
         if let Err(e) = system::ensure_file_permissions() {
-            spin.fail(e.to_string().as_str());
+            spin.fail("Failed to ensure file permissions");
             spin.finish();
-            return;
+            return Err(RuntipiError::Permissions(e.to_string()));
         }
     }
 
     spin.succeed("File permissions ok");
 
-    spin.set_message("Pulling images...");
-
-    let root_folder: PathBuf = current_dir().expect("Unable to get current directory");
-
-    let env_file_path = format!("{}/.env", root_folder.display());
-    let output = std::process::Command::new("docker")
-        .arg("compose")
-        .arg("--env-file")
-        .arg(&env_file_path)
-        .arg("pull")
-        .output();
-
-    match output {
-        Ok(output) => {
-            if !output.status.success() {
+    let root_folder: PathBuf = current_dir()?;
+
+    if !args.no_pull {
+        spin.set_message("Pulling images...");
+
+        let env_file_path = format!("{}/.env", root_folder.display());
+        let result = std::process::Command::new("docker")
+            .arg("compose")
+            .arg("--env-file")
+            .arg(&env_file_path)
+            .arg("pull")
+            .output();
+
+        match result {
+            Ok(result) => {
+                if !result.status.success() {
+                    spin.fail("Failed to pull images");
+                    spin.finish();
+                    return Err(RuntipiError::Compose(String::from_utf8_lossy(&result.stderr).to_string()));
+                }
+            }
+            Err(e) => {
                 spin.fail("Failed to pull images");
-
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                println!("\nDebug: {}", stderr);
-                return;
+                spin.finish();
+                return Err(RuntipiError::Docker(e.to_string()));
             }
         }
-        Err(e) => {
-            spin.fail("Failed to pull images");
-            spin.finish();
-            println!("\nError: {}", e);
-            return;
-        }
-    }
 
-    spin.succeed("Images pulled");
+        spin.succeed("Images pulled");
+    }
 
     // Stop and remove containers
     spin.set_message("Stopping existing containers...");
-    let container_names = vec![
-        "tipi-reverse-proxy",
-        "tipi-docker-proxy",
-        "tipi-db",
-        "tipi-redis",
-        "tipi-worker",
-        "tipi-dashboard",
-    ];
-
-    for container_name in container_names {
+    for container_name in CORE_CONTAINERS {
         let _ = std::process::Command::new("docker").arg("stop").arg(container_name).output();
         let _ = std::process::Command::new("docker").arg("rm").arg(container_name).output();
     }
@@ -107,55 +241,52 @@ pub fn run(args: StartArgs) {
     spin.succeed("Existing containers stopped");
 
     spin.set_message("Starting containers...");
-    let user_compose_file = root_folder.join("user-config").join("tipi-compose.yml");
-
-    let mut args = vec!["-f".to_string(), root_folder.join("docker-compose.yml").display().to_string()];
-
-    if user_compose_file.exists() {
-        args.push("-f".to_string());
-        args.push(user_compose_file.display().to_string());
-    }
-
-    args.push("--env-file".to_string());
-    args.push(env_file_path);
+    let mut args = docker::compose_file_args(&root_folder);
     args.push("up".to_string());
     args.push("--detach".to_string());
     args.push("--remove-orphans".to_string());
     args.push("--build".to_string());
 
-    let output = std::process::Command::new("docker")
-        .arg("compose")
-        .args(&args)
-        .output()
-        .map_err(|e| e.to_string());
+    let result = std::process::Command::new("docker").arg("compose").args(&args).output();
 
-    match output {
-        Ok(output) => {
-            if !output.status.success() {
+    match result {
+        Ok(result) => {
+            if !result.status.success() {
                 spin.fail("Failed to start containers");
-
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                println!("\nDebug: {}", stderr);
-                return;
+                spin.finish();
+                return Err(RuntipiError::Compose(String::from_utf8_lossy(&result.stderr).to_string()));
             }
         }
         Err(e) => {
             spin.fail("Failed to start containers");
             spin.finish();
-            println!("\nError: {}", e);
-            return;
+            return Err(RuntipiError::Docker(e.to_string()));
         }
     }
 
     spin.succeed("Containers started");
+
+    let dashboard_url = match dashboard_url(&env_map) {
+        Ok(dashboard_url) => dashboard_url,
+        Err(e) => {
+            spin.finish();
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = wait_until_healthy(&spin, &dashboard_url, timeout) {
+        spin.finish();
+        return Err(e);
+    }
+
     spin.finish();
-    println!("\n");
+    if !output::is_json() {
+        println!("\n");
+    }
 
-    let ip_and_port = format!(
-        "Visit http://{}:{} to access the dashboard",
-        env_map.get("INTERNAL_IP").unwrap(),
-        env_map.get("NGINX_PORT").unwrap()
-    );
+    output::data("dashboard_url", &dashboard_url);
+
+    let ip_and_port = format!("Visit {} to access the dashboard", dashboard_url);
 
     let box_title = "Runtipi started successfully".to_string();
     let box_body = format!(
@@ -167,4 +298,8 @@ pub fn run(args: StartArgs) {
 
     let console_box = ConsoleBox::new(box_title, box_body, 80, "green".to_string());
     console_box.print();
+
+    update::print_update_notice(&root_folder);
+
+    Ok(())
 }
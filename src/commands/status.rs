@@ -0,0 +1,128 @@
+use colored::Colorize;
+use prettytable::{format, row, Table};
+use serde::Serialize;
+
+use crate::components::output;
+use crate::error::RuntipiError;
+use crate::utils::constants::CORE_CONTAINERS;
+use crate::utils::docker;
+use crate::utils::worker_api::WorkerApi;
+
+#[derive(Debug, Serialize)]
+struct ComponentStatus {
+    name: String,
+    /// Whether the component is expected to run with the current docker-compose.yml
+    required: bool,
+    ok: bool,
+    state: String,
+    health: Option<String>,
+    details: Option<String>,
+}
+
+fn container_status(name: &str) -> ComponentStatus {
+    let required = docker::is_compose_container(name);
+
+    match docker::inspect_container(name) {
+        Ok(Some(container)) => ComponentStatus {
+            name: name.to_string(),
+            required,
+            ok: container.is_healthy(),
+            state: container.state,
+            health: container.health,
+            details: None,
+        },
+        Ok(None) => ComponentStatus {
+            name: name.to_string(),
+            required,
+            ok: !required,
+            state: if required { "missing".to_string() } else { "not deployed".to_string() },
+            health: None,
+            details: None,
+        },
+        Err(e) => ComponentStatus {
+            name: name.to_string(),
+            required,
+            ok: false,
+            state: "unknown".to_string(),
+            health: None,
+            details: Some(e.to_string()),
+        },
+    }
+}
+
+fn worker_api_status() -> ComponentStatus {
+    let result = WorkerApi::from_current_dir().and_then(|api| api.healthcheck());
+
+    ComponentStatus {
+        name: "worker-api".to_string(),
+        required: true,
+        ok: result.is_ok(),
+        state: if result.is_ok() {
+            "reachable".to_string()
+        } else {
+            "unreachable".to_string()
+        },
+        health: None,
+        details: result.err().map(|e| e.to_string()),
+    }
+}
+
+pub fn run() -> Result<(), RuntipiError> {
+    let mut components: Vec<ComponentStatus> = CORE_CONTAINERS.iter().map(|name| container_status(name)).collect();
+    components.push(worker_api_status());
+
+    let nothing_running = components
+        .iter()
+        .filter(|c| c.required && c.name != "worker-api")
+        .all(|c| c.state != "running");
+    let proxy_down = components.iter().any(|c| c.name == "tipi-reverse-proxy" && c.state != "running");
+
+    let overall = if nothing_running || proxy_down {
+        "DOWN"
+    } else if components.iter().all(|c| c.ok) {
+        "OK"
+    } else {
+        "DEGRADED"
+    };
+
+    let failing = components
+        .iter()
+        .filter(|c| !c.ok)
+        .map(|c| c.name.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+
+    if output::is_json() {
+        output::data("status", overall);
+        output::data("components", &components);
+    } else {
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_BOX_CHARS);
+        table.set_titles(row!["Component", "State", "Health", "Details"]);
+
+        for component in &components {
+            let state = if component.ok { component.state.green() } else { component.state.red() };
+            table.add_row(row![
+                component.name,
+                state,
+                component.health.as_deref().unwrap_or("-"),
+                component.details.as_deref().unwrap_or("")
+            ]);
+        }
+
+        table.printstd();
+
+        let overall_colored = match overall {
+            "OK" => overall.green(),
+            "DEGRADED" => overall.yellow(),
+            _ => overall.red(),
+        };
+        println!("\nOverall status: {}", overall_colored.bold());
+    }
+
+    match overall {
+        "OK" => Ok(()),
+        "DEGRADED" => Err(RuntipiError::Degraded(format!("Some components are not healthy: {}", failing))),
+        _ => Err(RuntipiError::Down(format!("Tipi is down. Failing components: {}", failing))),
+    }
+}
@@ -1,51 +1,40 @@
 use crate::components::spinner;
+use crate::error::RuntipiError;
+use crate::utils::constants::CORE_CONTAINERS;
 
-pub fn run() {
+pub fn run() -> Result<(), RuntipiError> {
     let spin = spinner::new("");
 
     spin.set_message("Stopping containers...");
 
     let args = vec!["down", "--remove-orphans", "--rmi", "local"];
 
-    let output = std::process::Command::new("docker")
-        .arg("compose")
-        .args(&args)
-        .output()
-        .map_err(|e| e.to_string());
+    let result = std::process::Command::new("docker").arg("compose").args(&args).output();
 
-    match output {
-        Ok(output) => {
-            if !output.status.success() {
+    match result {
+        Ok(result) => {
+            if !result.status.success() {
                 spin.fail("Failed to stop containers. Please try to stop them manually");
                 spin.finish();
 
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                println!("\nDebug: {}", stderr);
-                return;
+                return Err(RuntipiError::Compose(String::from_utf8_lossy(&result.stderr).to_string()));
             }
         }
         Err(e) => {
             spin.fail("Failed to stop containers. Please try to stop them manually");
             spin.finish();
 
-            println!("\nDebug: {}", e);
-            return;
+            return Err(RuntipiError::Docker(e.to_string()));
         }
     }
 
-    let container_names = vec![
-        "tipi-reverse-proxy",
-        "tipi-docker-proxy",
-        "tipi-db",
-        "tipi-redis",
-        "tipi-worker",
-        "tipi-dashboard",
-    ];
-
-    for container_name in container_names {
+    for container_name in CORE_CONTAINERS {
         let _ = std::process::Command::new("docker").arg("stop").arg(container_name).output();
         let _ = std::process::Command::new("docker").arg("rm").arg(container_name).output();
     }
 
     spin.succeed("Tipi successfully stopped");
+    spin.finish();
+
+    Ok(())
 }
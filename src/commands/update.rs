@@ -1,165 +1,711 @@
-use reqwest::blocking::Client;
-use std::path::PathBuf;
+use reqwest::header::HeaderValue;
+use std::path::{Path, PathBuf};
 use std::{env::current_dir, fs::File};
 
+use colored::Colorize;
 use self_update::self_replace::self_replace;
-use serde::Deserialize;
+use self_update::update::{Release, ReleaseAsset};
+use semver::Version;
+use serde::{Deserialize, Serialize};
 
+use crate::args::{RollbackArgs, VersionEnum};
+use crate::commands::{export_images, start, stop};
 use crate::components::console_box::ConsoleBox;
-use crate::utils::env;
-use crate::{components::spinner, utils::system::get_architecture};
+use crate::components::{markdown, output, prompt, spinner};
+use crate::error::RuntipiError;
+use crate::utils::constants::VERSION;
+use crate::utils::release::{ReleaseChannel, UpdateKind};
+use crate::utils::release_source::ReleaseSource;
+use crate::utils::schemas::SettingsSchema;
+use crate::utils::system::get_architecture;
+use crate::utils::{docker, env, release, rollback};
 
-#[derive(Deserialize, Debug)]
-struct GithubRelease {
-    tag_name: String,
+const BREAKING_UPDATES_URL: &str = "https://runtipi.io/docs/reference/breaking-updates";
+const UPDATE_CHECK_FILE: &str = "update-check.json";
+const UPDATE_CHECK_INTERVAL_SECONDS: i64 = 24 * 60 * 60;
+
+/// Result of the last update check, cached in state/update-check.json
+#[derive(Debug, Serialize, Deserialize)]
+struct UpdateCheckCache {
+    checked_at: i64,
+    channel: ReleaseChannel,
+    latest_version: String,
 }
 
 #[derive(Debug)]
 pub struct UpdateArgs {
-    pub version: String,
+    pub version: VersionEnum,
     pub env_file: Option<PathBuf>,
     pub no_permissions: bool,
+    pub skip_verification: bool,
+    pub allow_downgrade: bool,
+    pub channel: Option<ReleaseChannel>,
+    pub from_file: Option<PathBuf>,
+    pub yes: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ReleaseNotes {
+    version: String,
+    date: String,
+    breaking: bool,
+    notes: Option<String>,
 }
 
-fn is_major_bump(current_version: &str, new_version: &str) -> bool {
-    let current_version = current_version.split(".").collect::<Vec<&str>>();
-    let new_version = new_version.split(".").collect::<Vec<&str>>();
+fn download(url: &str, destination: &Path, source: &ReleaseSource) -> Result<(), RuntipiError> {
+    let file = File::create(destination)?;
+
+    let mut download = self_update::Download::from_url(url);
+    download.set_header(reqwest::header::ACCEPT, HeaderValue::from_static("application/octet-stream"));
+
+    if let Some(token) = &source.token {
+        let value =
+            HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|e| RuntipiError::InvalidInput(format!("Invalid release token: {}", e)))?;
+        download.set_header(reqwest::header::AUTHORIZATION, value);
+    }
+
+    download.download_to(&file).map_err(|e| RuntipiError::Network(e.to_string()))
+}
+
+/**
+* Download the checksum file of an asset and its signature, when the release publishes them, next to the asset
+*/
+fn download_checksums(assets: &[ReleaseAsset], asset_name: &str, destination: &Path, source: &ReleaseSource) -> Result<(), RuntipiError> {
+    let find_asset = |name: &str| assets.iter().find(|a| a.name == name);
+
+    let checksum_asset = match release::checksum_file_names(asset_name).iter().find_map(|name| find_asset(name)) {
+        Some(checksum_asset) => checksum_asset,
+        None => return Ok(()),
+    };
+    download(&checksum_asset.download_url, &destination.join(&checksum_asset.name), source)?;
+
+    if let Some(signature_asset) = find_asset(&release::signature_file_name(&checksum_asset.name)) {
+        download(&signature_asset.download_url, &destination.join(&signature_asset.name), source)?;
+    }
+
+    Ok(())
+}
+
+/**
+* Run `start` with the CLI binary currently installed in the root folder
+*/
+fn start_cli(env_file: &Option<PathBuf>, no_permissions: bool, no_pull: bool) -> Result<(), RuntipiError> {
+    let mut run_args = vec!["start".to_string()];
+    if no_permissions {
+        run_args.push("--no-permissions".to_string());
+    }
+
+    if no_pull {
+        run_args.push("--no-pull".to_string());
+    }
+
+    if let Some(env_file) = env_file {
+        run_args.push("--env-file".to_string());
+        run_args.push(env_file.display().to_string());
+    }
+
+    let result = std::process::Command::new("./runtipi-cli").args(run_args).output()?;
 
-    if current_version[0] < new_version[0] {
-        return true;
+    if !result.status.success() {
+        return Err(RuntipiError::Other(format!(
+            "{}{}",
+            String::from_utf8_lossy(&result.stdout),
+            String::from_utf8_lossy(&result.stderr)
+        )));
     }
 
-    false
+    Ok(())
 }
 
-pub fn run(args: UpdateArgs) {
+/**
+* Put back the binary and instance files saved in a rollback snapshot
+*/
+fn restore_snapshot(root_folder: &Path, snapshot: &Path) -> Result<(), RuntipiError> {
+    let binary = rollback::verified_binary(snapshot).map_err(|e| RuntipiError::Integrity(e.to_string()))?;
+
+    rollback::restore_snapshot_files(root_folder, snapshot)?;
+    self_replace(binary)?;
+
+    Ok(())
+}
+
+fn read_settings(root_folder: &Path) -> Option<SettingsSchema> {
+    let content = std::fs::read_to_string(root_folder.join("state").join("settings.json")).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/**
+* Where to look for releases, configured in settings.json or with the RUNTIPI_RELEASE_* environment variables
+*/
+fn release_source(root_folder: &Path) -> Result<ReleaseSource, RuntipiError> {
+    ReleaseSource::resolve(read_settings(root_folder).and_then(|settings| settings.release_source)).map_err(RuntipiError::InvalidInput)
+}
+
+fn fetch_releases(source: &ReleaseSource) -> Result<Vec<Release>, RuntipiError> {
+    source.fetch_releases().map_err(RuntipiError::Network)
+}
+
+/**
+* Channel persisted in settings.json, stable by default
+*/
+fn configured_channel(root_folder: &Path) -> ReleaseChannel {
+    read_settings(root_folder)
+        .and_then(|settings| settings.update_channel)
+        .unwrap_or(ReleaseChannel::Stable)
+}
+
+/**
+* Version of tipi installed in the root folder, falling back to the one bundled with this CLI
+*/
+/**
+* Version of the tipi instance in the root folder, from its VERSION file
+*/
+pub fn installed_version(root_folder: &Path) -> String {
+    std::fs::read_to_string(root_folder.join("VERSION"))
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|_| VERSION.trim().to_string())
+}
+
+pub fn check(channel: Option<ReleaseChannel>) -> Result<(), RuntipiError> {
     let spin = spinner::new("");
+    let root_folder = current_dir()?;
+    let channel = channel.unwrap_or_else(|| configured_channel(&root_folder));
 
-    spin.set_message("Grabbing releases from GitHub");
+    let source = match release_source(&root_folder) {
+        Ok(source) => source,
+        Err(e) => {
+            spin.fail("Invalid release source");
+            spin.finish();
+            return Err(e);
+        }
+    };
 
-    let releases = self_update::backends::github::ReleaseList::configure()
-        .repo_owner("runtipi")
-        .repo_name("cli")
-        .build()
-        .unwrap();
+    spin.set_message(format!("Grabbing releases from {}", source).as_str());
 
-    let fetch = releases.fetch().unwrap();
+    let releases = match fetch_releases(&source) {
+        Ok(releases) => releases,
+        Err(e) => {
+            spin.fail("Failed to fetch releases");
+            spin.finish();
+            return Err(e);
+        }
+    };
 
-    // Find args.version in releases
-    // If args.version is latest, use the latest non-prerelease version
-    let wanted_version = if args.version == "latest" {
-        let url = "https://api.github.com/repos/runtipi/runtipi/releases/latest";
+    let latest = match channel.latest(&releases) {
+        Some(latest) => latest,
+        None => {
+            spin.fail("No release found");
+            spin.finish();
+            return Err(RuntipiError::Network(format!("{} did not return any {} release", source, channel)));
+        }
+    };
 
-        let http_client = Client::builder().user_agent("reqwest").build().unwrap();
-        let response = http_client.get(url).send().unwrap();
+    output::data("channel", channel);
 
-        if response.status().is_success() {
-            let latest: GithubRelease = response.json().unwrap();
+    // Nightly builds are not versioned, only their publication date tells them apart
+    let latest_version = match release::parse_version(&latest.version) {
+        Some(version) => version,
+        None => {
+            spin.succeed(format!("Latest {} build was published on {}", latest.version, latest.date).as_str());
+            spin.finish();
+            output::data("latest_version", &latest.version);
+            output::data("published_at", &latest.date);
+            output::info(&format!("\nRun `runtipi-cli update {}` to update", latest.version));
+            return Ok(());
+        }
+    };
 
-            latest.tag_name[1..].to_string()
-        } else if args.version == "nightly" {
-            "nightly".to_string()
-        } else {
-            spin.fail("Failed to fetch latest release");
+    let installed = installed_version(&root_folder);
+    let current_version = match release::parse_version(&installed) {
+        Some(version) => version,
+        None => {
+            spin.fail("Unable to read the installed version");
             spin.finish();
+            return Err(RuntipiError::Other(format!("{} is not a valid version", installed)));
+        }
+    };
 
-            return;
+    output::data("current_version", current_version.to_string());
+    output::data("latest_version", latest_version.to_string());
+
+    let kind = match release::update_kind(&current_version, &latest_version) {
+        Some(kind) => kind,
+        None => {
+            spin.succeed(format!("You are running the latest version ({})", current_version).as_str());
+            spin.finish();
+            output::data("update_available", false);
+            return Ok(());
         }
+    };
+
+    spin.succeed(format!("A new {} update is available: {} -> {}", kind, current_version, latest_version).as_str());
+    spin.finish();
+
+    output::data("update_available", true);
+    output::data("update_kind", kind);
+    output::data("release_notes", &latest.body);
+
+    if let Some(notes) = latest.body.as_deref().filter(|notes| !notes.trim().is_empty() && !output::is_json()) {
+        output::info(&format!(
+            "\n--- Release notes of {} ---\n{}",
+            latest_version,
+            markdown::render(notes.trim())
+        ));
+    }
+
+    if kind == UpdateKind::Major {
+        output::info(&format!(
+            "\n⚠️ This is a major update. Follow the instructions at {} to update",
+            BREAKING_UPDATES_URL
+        ));
     } else {
-        args.version
+        output::info(&format!("\nRun `runtipi-cli update latest --channel {}` to update", channel));
+    }
+
+    Ok(())
+}
+
+/**
+* Latest version of a channel, fetched from the release source at most once a day
+*/
+fn cached_latest_version(root_folder: &Path, channel: ReleaseChannel) -> Option<Version> {
+    let cache_path = root_folder.join("state").join(UPDATE_CHECK_FILE);
+    let now = chrono::Utc::now().timestamp();
+
+    let cache = std::fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<UpdateCheckCache>(&content).ok())
+        .filter(|cache| cache.channel == channel && now - cache.checked_at < UPDATE_CHECK_INTERVAL_SECONDS);
+
+    let latest_version = match cache {
+        Some(cache) => cache.latest_version,
+        None => {
+            let releases = release_source(root_folder).and_then(|source| fetch_releases(&source)).ok()?;
+            let latest_version = channel.latest(&releases)?.version.clone();
+            let cache = UpdateCheckCache {
+                checked_at: now,
+                channel,
+                latest_version: latest_version.clone(),
+            };
+            let _ = serde_json::to_string(&cache).map(|content| std::fs::write(&cache_path, content));
+            latest_version
+        }
     };
 
-    let env_map = env::get_env_map();
+    release::parse_version(&latest_version)
+}
 
-    let current_version = env_map.get("TIPI_VERSION").unwrap().replace("v", "");
-    if is_major_bump(&current_version, &wanted_version) {
-        spin.fail("You are trying to update to a new major version. Please update manually using the update instructions on the website. https://runtipi.io/docs/reference/breaking-updates");
-        spin.finish();
+/**
+* Print a notice when a newer version is available and the user opted in with `checkForUpdates` in settings.json.
+* Never fails, an update check must not get in the way of starting tipi
+*/
+pub fn print_update_notice(root_folder: &Path) {
+    let enabled = read_settings(root_folder).and_then(|settings| settings.check_for_updates);
+
+    if enabled != Some(true) {
         return;
     }
 
-    let release = fetch.iter().find(|r| r.version.as_str() == wanted_version);
+    let current_version = match release::parse_version(&installed_version(root_folder)) {
+        Some(version) => version,
+        None => return,
+    };
 
-    match release {
-        Some(release) => {
-            spin.succeed(format!("Found version {}", release.version).as_str());
+    let latest_version = match cached_latest_version(root_folder, configured_channel(root_folder)) {
+        Some(version) => version,
+        None => return,
+    };
+
+    let kind = release::update_kind(&current_version, &latest_version);
+
+    // Same keys as `update --check`
+    output::data("update_available", kind.is_some());
+    output::data("latest_version", latest_version.to_string());
+
+    if let Some(kind) = kind {
+        output::data("update_kind", kind);
+        output::info(&format!(
+            "🆕 Runtipi {} is available ({} update). Run `runtipi-cli update --check` for details",
+            latest_version, kind
+        ));
+    }
+}
+
+/**
+* Find the release matching the requested version. `latest` is the most recent release of the channel
+*/
+fn resolve_release<'a>(releases: &'a [Release], version: &VersionEnum, channel: ReleaseChannel) -> Option<&'a Release> {
+    match version {
+        VersionEnum::Latest => channel.latest(releases),
+        VersionEnum::Nightly => ReleaseChannel::Nightly.latest(releases),
+        VersionEnum::Version(wanted) => releases
+            .iter()
+            .find(|r| release::parse_version(&r.version).is_some_and(|version| &version == wanted)),
+    }
+}
+
+/**
+* First release of every major version between `current` (excluded) and `target` (included)
+*/
+fn breaking_updates(releases: &[Release], current: &Version, target: &Version) -> Vec<Version> {
+    (current.major + 1..=target.major)
+        .map(|major| {
+            releases
+                .iter()
+                .filter_map(|r| release::parse_version(&r.version))
+                .filter(|version| version.major == major && version.pre.is_empty())
+                .min()
+                .unwrap_or_else(|| Version::new(major, 0, 0))
+        })
+        .collect()
+}
+
+/**
+* Refuse downgrades unless allowed, and changes of major version which need the manual breaking update steps
+*/
+fn check_version_change(releases: &[Release], current: &Version, target: &Version, allow_downgrade: bool) -> Result<(), RuntipiError> {
+    if target.major > current.major {
+        let path = breaking_updates(releases, current, target)
+            .iter()
+            .map(|version| format!("v{}", version))
+            .collect::<Vec<String>>();
+
+        return Err(RuntipiError::InvalidInput(format!(
+            "v{} is a major update. Breaking updates to go through from v{}: {}\nPlease update manually using the update instructions on the website. {}",
+            target,
+            current,
+            path.join(" -> "),
+            BREAKING_UPDATES_URL
+        )));
+    }
+
+    if target.major < current.major {
+        return Err(RuntipiError::InvalidInput(format!(
+            "Downgrading from v{} to another major version (v{}) is not supported. Restore a backup instead",
+            current, target
+        )));
+    }
+
+    if target < current && !allow_downgrade {
+        return Err(RuntipiError::InvalidInput(format!(
+            "v{} is older than the installed version v{}. Run the update again with --allow-downgrade if this is intended",
+            target, current
+        )));
+    }
+
+    Ok(())
+}
+
+/**
+* Releases from the installed version (excluded) to the target (included), most recent first.
+* Pre-releases are only listed when updating to a pre-release
+*/
+fn releases_between<'a>(releases: &'a [Release], installed: &str, target: &'a Release) -> Vec<&'a Release> {
+    let (current_version, target_version) = match (release::parse_version(installed), release::parse_version(&target.version)) {
+        (Some(current_version), Some(target_version)) => (current_version, target_version),
+        _ => return vec![target],
+    };
+
+    let mut between = releases
+        .iter()
+        .filter_map(|r| release::parse_version(&r.version).map(|version| (r, version)))
+        .filter(|(_, version)| *version > current_version && *version <= target_version)
+        .filter(|(_, version)| version.pre.is_empty() || !target_version.pre.is_empty())
+        .collect::<Vec<(&Release, Version)>>();
+    between.sort_by(|a, b| b.1.cmp(&a.1));
+
+    between.into_iter().map(|(r, _)| r).collect()
+}
+
+/**
+* Print the release notes of every release part of the update. Returns whether one of them mentions a breaking change
+*/
+fn show_release_notes(spin: &spinner::CustomSpinner, releases: &[&Release]) -> bool {
+    let notes = releases
+        .iter()
+        .map(|r| ReleaseNotes {
+            version: r.version.clone(),
+            date: r.date.clone(),
+            breaking: r.body.as_deref().is_some_and(|body| body.lines().any(markdown::is_breaking)),
+            notes: r.body.clone().filter(|body| !body.trim().is_empty()),
+        })
+        .collect::<Vec<ReleaseNotes>>();
+
+    let breaking = notes.iter().any(|n| n.breaking);
+
+    if output::is_json() {
+        output::data("release_notes", &notes);
+        return breaking;
+    }
+
+    spin.suspend(|| {
+        for release_notes in &notes {
+            let title = format!(" {} {} ", release_notes.version, release_notes.date.split('T').next().unwrap_or_default());
+            println!("\n{}{}\n", title.bold(), "─".repeat(60usize.saturating_sub(title.len())).dimmed());
+
+            match &release_notes.notes {
+                Some(body) => println!("{}", markdown::render(body)),
+                None => println!("{}", "No release notes".dimmed()),
+            }
         }
-        None => {
-            spin.fail(format!("Version {} not found", wanted_version).as_str());
-            spin.finish();
-            return;
+
+        if breaking {
+            println!(
+                "\n{}",
+                "⚠️ These releases mention breaking changes. Read them carefully before updating"
+                    .red()
+                    .bold()
+            );
         }
-    }
 
-    let release = release.unwrap();
+        println!();
+    });
 
-    let arch = get_architecture().unwrap_or("x86_64".to_string()).to_string();
-    let arch = if arch == "arm64" { "aarch64".to_string() } else { "x86_64".to_string() };
+    breaking
+}
 
-    spin.set_message(format!("Downloading {} release", arch).as_str());
+/**
+* Ask the user to confirm the update, unless --yes was given
+*/
+fn confirm_update(spin: &spinner::CustomSpinner, version: &str, breaking: bool, args: &UpdateArgs) -> Result<(), RuntipiError> {
+    if args.yes {
+        return Ok(());
+    }
+
+    if !prompt::can_prompt() {
+        spin.fail("Update not confirmed");
+        spin.finish();
+        return Err(RuntipiError::InvalidInput(format!(
+            "Updating to {} needs a confirmation. Run the update again with --yes",
+            version
+        )));
+    }
+
+    spin.set_message("Waiting for confirmation");
 
-    let asset = release.asset_for(arch.as_str(), Some("linux"));
+    let question = if breaking {
+        format!("Update to {} despite the breaking changes?", version)
+    } else {
+        format!("Update to {}?", version)
+    };
+    let confirmed = spin.suspend(|| prompt::confirm(&question))?;
 
-    if asset.is_none() {
-        spin.fail(format!("No asset found for {} {} on release {}", arch, "linux", release.version).as_str());
+    if !confirmed {
+        spin.fail("Update cancelled");
         spin.finish();
-        return;
+        return Err(RuntipiError::Other("Update cancelled, nothing was changed".to_string()));
     }
 
-    let asset = asset.unwrap();
+    Ok(())
+}
 
-    let current_dir = current_dir().expect("Unable to get current directory");
+/**
+* Architecture of the release assets matching this machine
+*/
+fn host_arch() -> String {
+    let arch = get_architecture().unwrap_or("x86_64".to_string()).to_string();
+    if arch == "arm64" {
+        "aarch64".to_string()
+    } else {
+        "x86_64".to_string()
+    }
+}
 
-    let tmp_dir = tempfile::Builder::new().prefix("self_update").tempdir_in(&current_dir).unwrap();
-    let tmp_tarball_path = tmp_dir.path().join(&asset.name);
-    let tmp_tarball = File::create(&tmp_tarball_path).unwrap();
+fn verify_release(spin: &spinner::CustomSpinner, release_dir: &Path, asset_name: &str, skip_verification: bool) -> Result<(), RuntipiError> {
+    spin.set_message("Verifying release checksum");
 
-    let output = self_update::Download::from_url(&asset.download_url)
-        .set_header(reqwest::header::ACCEPT, "application/octet-stream".parse().unwrap())
-        .download_to(&tmp_tarball);
+    match release::verify_release_dir(release_dir, asset_name) {
+        Ok(_) => {
+            spin.succeed("Release checksum verified");
+            Ok(())
+        }
+        Err(e) if skip_verification => {
+            spin.fail(format!("Skipping release verification: {}", e).as_str());
+            Ok(())
+        }
+        Err(e) => {
+            spin.fail("Release verification failed");
+            spin.finish();
+            Err(RuntipiError::Integrity(format!(
+                "{}\nIf you trust this release, run the update again with --skip-verification",
+                e
+            )))
+        }
+    }
+}
 
-    match output {
+/**
+* Check an image archive of a bundle against the manifest written next to it by export-images, or else against
+* the checksum files of the bundle, the same way as the CLI release
+*/
+fn verify_image_archive(spin: &spinner::CustomSpinner, bundle_dir: &Path, archive: &Path, skip_verification: bool) -> Result<(), RuntipiError> {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+    spin.set_message(format!("Verifying {}", name).as_str());
+
+    let manifest_path = archive.with_extension("json");
+    let result = if manifest_path.is_file() {
+        export_images::verify_archive(archive, &manifest_path)
+    } else {
+        release::verify_release_dir(bundle_dir, &name)
+    };
+
+    match result {
         Ok(_) => {
-            spin.succeed(format!("Downloaded {}", &asset.name).as_str());
+            spin.succeed(format!("Verified {}", name).as_str());
+            Ok(())
+        }
+        Err(e) if skip_verification => {
+            spin.fail(format!("Skipping verification of {}: {}", name, e).as_str());
+            Ok(())
         }
         Err(e) => {
-            spin.fail("Failed to download release");
+            spin.fail(format!("Verification of {} failed", name).as_str());
+            spin.finish();
+            Err(RuntipiError::Integrity(format!(
+                "{}\nIf you trust this bundle, run the update again with --skip-verification",
+                e
+            )))
+        }
+    }
+}
+
+/**
+* Update from a bundle created for hosts without internet access. The release source is not used:
+*
+* bundle.tar.gz
+* ├── runtipi-cli-linux-x86_64.tar.gz
+* ├── runtipi-cli-linux-x86_64.tar.gz.sha256
+* ├── VERSION                    (optional, version of the release)
+* ├── *.tar                      (optional, images saved with `docker save` or `runtipi-cli export-images`)
+* └── *.json                     (manifests written by export-images, or a checksum file listing the image archives)
+*/
+fn run_from_bundle(bundle: &Path, args: &UpdateArgs) -> Result<(), RuntipiError> {
+    let spin = spinner::new("");
+    let current_dir = current_dir()?;
+
+    spin.set_message("Extracting bundle");
+
+    let tmp_dir = tempfile::Builder::new().prefix("self_update").tempdir_in(&current_dir)?;
+
+    if let Err(e) = release::unpack_bundle(bundle, tmp_dir.path()) {
+        spin.fail("Failed to extract bundle");
+        spin.finish();
+        return Err(RuntipiError::InvalidInput(format!("{}: {}", bundle.display(), e)));
+    }
+
+    let arch = host_arch();
+    let asset_name = match release::find_bundle_asset(tmp_dir.path(), &arch) {
+        Some(asset_name) => {
+            spin.succeed(format!("Found {} in bundle", asset_name).as_str());
+            asset_name
+        }
+        None => {
+            spin.fail("Invalid bundle");
             spin.finish();
-            println!("\nError: {}", e);
-            return;
+            return Err(RuntipiError::InvalidInput(format!(
+                "{} does not contain a CLI release for linux {}",
+                bundle.display(),
+                arch
+            )));
+        }
+    };
+
+    verify_release(&spin, tmp_dir.path(), &asset_name, args.skip_verification)?;
+
+    let version = std::fs::read_to_string(tmp_dir.path().join("VERSION")).ok().map(|v| v.trim().to_string());
+    let installed = installed_version(&current_dir);
+
+    match (release::parse_version(&installed), version.as_deref().and_then(release::parse_version)) {
+        (Some(current_version), Some(target_version)) => {
+            if let Err(e) = check_version_change(&[], &current_version, &target_version, args.allow_downgrade) {
+                spin.fail(format!("Cannot update from {} to {}", current_version, target_version).as_str());
+                spin.finish();
+                return Err(e);
+            }
+        }
+        _ => {
+            spin.fail("Unable to compare the bundle version with the installed version. Make sure this is the version you want");
         }
     }
 
+    confirm_update(&spin, version.as_deref().unwrap_or("the bundled version"), false, args)?;
+
     spin.set_message("Extracting tarball");
 
-    let output = std::process::Command::new("tar")
-        .arg("-xzf")
-        .arg(&tmp_tarball_path)
-        .arg("-C")
-        .arg(&current_dir)
-        .output();
+    let extract_dir = tmp_dir.path().join("extracted");
+    std::fs::create_dir(&extract_dir)?;
 
-    match output {
-        Ok(_) => {
+    let new_executable_path = match release::extract_binary(&tmp_dir.path().join(&asset_name), &asset_name, &arch, &extract_dir) {
+        Ok(path) => {
             spin.succeed("Extracted tarball");
+            path
         }
         Err(e) => {
             spin.fail("Failed to extract tarball");
             spin.finish();
-            println!("\nError: {}", e);
-            return;
+            return Err(RuntipiError::Integrity(e));
         }
+    };
+
+    let image_archives = release::bundle_image_archives(tmp_dir.path())?;
+
+    // Images are loaded before the CLI is replaced, check them all first
+    for archive in &image_archives {
+        verify_image_archive(&spin, tmp_dir.path(), archive, args.skip_verification)?;
+    }
+
+    for archive in &image_archives {
+        let name = archive.file_name().unwrap_or_default().to_string_lossy().to_string();
+        spin.set_message(format!("Loading images from {}", name).as_str());
+
+        if let Err(e) = docker::load_images(archive) {
+            spin.fail(format!("Failed to load images from {}", name).as_str());
+            spin.finish();
+            return Err(RuntipiError::Docker(e.to_string()));
+        }
+
+        spin.succeed(format!("Loaded images from {}", name).as_str());
     }
 
-    // asset.name with no extension
-    let bin_name = asset.name.split(".").collect::<Vec<&str>>()[0];
-    let new_executable_path = current_dir.join(&bin_name);
+    let version = version.unwrap_or_else(|| "from bundle".to_string());
+
+    // With bundled images, skip `compose pull`: it would replace them or fail offline.
+    // `compose up` still pulls the images that are missing from a partial bundle
+    install_and_start(&spin, &current_dir, &new_executable_path, &version, args, !image_archives.is_empty())
+}
+
+/**
+* Replace the CLI with a verified binary and start tipi with it, going back to the current version if it fails to start
+*/
+fn install_and_start(
+    spin: &spinner::CustomSpinner,
+    current_dir: &Path,
+    new_executable_path: &Path,
+    version: &str,
+    args: &UpdateArgs,
+    no_pull: bool,
+) -> Result<(), RuntipiError> {
+    let current_tipi_version = VERSION.trim().to_string();
+
+    spin.suspend(stop::run)?;
+
+    spin.set_message("Saving current version for rollback");
+
+    let snapshot = match rollback::create_snapshot(current_dir, &current_tipi_version) {
+        Ok(snapshot) => {
+            spin.succeed(format!("Saved rollback snapshot of {}", current_tipi_version).as_str());
+            snapshot
+        }
+        Err(e) => {
+            spin.fail("Failed to save rollback snapshot");
+            spin.finish();
+            return Err(e.into());
+        }
+    };
 
     spin.set_message("Replacing old CLI");
-    std::process::Command::new("chmod").arg("+x").arg(&new_executable_path);
 
-    let result = self_replace(&new_executable_path);
-    let _ = std::fs::remove_file(new_executable_path);
+    let result = self_replace(new_executable_path);
 
     match result {
         Ok(_) => {
@@ -168,61 +714,344 @@ pub fn run(args: UpdateArgs) {
         Err(e) => {
             spin.fail("Failed to replace old CLI");
             spin.finish();
-            println!("\nError: {}", e);
-            return;
+            return Err(e.into());
         }
     }
 
     spin.set_message("Starting Tipi... This may take a while.");
 
-    // Start new CLI
-    let mut run_args = vec!["start".to_string()];
-    if args.no_permissions {
-        run_args.push("--no-permissions".to_string());
+    // Run command start on new CLI, going back to the previous version if it fails
+    if let Err(e) = start_cli(&args.env_file, args.no_permissions, no_pull) {
+        spin.fail("Failed to start new CLI");
+        spin.set_message(format!("Rolling back to {}...", current_tipi_version).as_str());
+
+        let rollback_result = restore_snapshot(current_dir, &snapshot).and_then(|_| start_cli(&args.env_file, args.no_permissions, no_pull));
+
+        let error = match rollback_result {
+            Ok(_) => {
+                spin.succeed(format!("Rolled back to {}", current_tipi_version).as_str());
+                format!("Update to {} failed and tipi was rolled back to {}: {}", version, current_tipi_version, e)
+            }
+            Err(rollback_error) => {
+                spin.fail(format!("Failed to roll back to {}", current_tipi_version).as_str());
+                format!(
+                    "Update to {} failed: {}\nRolling back to {} failed as well: {}\nRun `runtipi-cli update rollback` to try again",
+                    version, e, current_tipi_version, rollback_error
+                )
+            }
+        };
+
+        spin.finish();
+        return Err(RuntipiError::Other(error));
     }
 
-    let env_file = args.env_file;
+    spin.finish();
 
-    if env_file.is_some() {
-        run_args.push("--env-file".to_string());
-        run_args.push(env_file.unwrap().display().to_string());
+    if !output::is_json() {
+        println!("\n");
     }
 
-    // Run command start on new CLI
-    let result = std::process::Command::new("./runtipi-cli").args(run_args).output();
+    let env_map = env::get_env_map().map_err(|e| RuntipiError::Env(e.to_string()))?;
+    let dashboard_url = start::dashboard_url(&env_map)?;
+    output::data("dashboard_url", &dashboard_url);
 
-    match result {
-        Ok(output) => {
-            if !output.status.success() {
-                spin.fail("Failed to start new CLI");
-                println!("\nDebug: {}", String::from_utf8_lossy(&output.stderr));
+    output::data("version", version);
+
+    let ip_and_port = format!("Visit {} to access the dashboard", dashboard_url);
+
+    let box_title = "Runtipi started successfully".to_string();
+    let box_body = format!(
+        "{}\n\nYou are now running version {}\n\n{}",
+        ip_and_port, version, "Tipi is entirely written in TypeScript and we are looking for contributors!"
+    );
+
+    let console_box = ConsoleBox::new(box_title, box_body, 80, "green".to_string());
+    console_box.print();
+
+    Ok(())
+}
+
+pub fn run(args: UpdateArgs) -> Result<(), RuntipiError> {
+    if let Some(bundle) = &args.from_file {
+        return run_from_bundle(bundle, &args);
+    }
+
+    let spin = spinner::new("");
+    let current_dir = current_dir()?;
+
+    let source = match release_source(&current_dir) {
+        Ok(source) => source,
+        Err(e) => {
+            spin.fail("Invalid release source");
+            spin.finish();
+            return Err(e);
+        }
+    };
+
+    spin.set_message(format!("Grabbing releases from {}", source).as_str());
+
+    let fetch = match fetch_releases(&source) {
+        Ok(fetch) => fetch,
+        Err(e) => {
+            spin.fail("Failed to fetch releases");
+            spin.finish();
+            return Err(e);
+        }
+    };
+
+    let channel = args.channel.unwrap_or_else(|| configured_channel(&current_dir));
+
+    let release = match resolve_release(&fetch, &args.version, channel) {
+        Some(release) => {
+            spin.succeed(format!("Found version {}", release.version).as_str());
+            release
+        }
+        None => {
+            spin.fail(format!("Version {} not found", args.version).as_str());
+            spin.finish();
+            return Err(RuntipiError::InvalidInput(format!("Version {} does not exist", args.version)));
+        }
+    };
+
+    let env_map = env::get_env_map().unwrap_or_default();
+
+    let installed = env_map.get("TIPI_VERSION").cloned().unwrap_or_else(|| installed_version(&current_dir));
+
+    // Nightly builds are not versioned, they can't be compared to the installed version
+    match (release::parse_version(&installed), release::parse_version(&release.version)) {
+        (Some(current_version), Some(target_version)) => {
+            if let Err(e) = check_version_change(&fetch, &current_version, &target_version, args.allow_downgrade) {
+                spin.fail(format!("Cannot update from {} to {}", current_version, target_version).as_str());
+                spin.finish();
+                return Err(e);
             }
         }
+        _ => {
+            spin.fail(
+                format!(
+                    "Unable to compare {} with the installed version {}. Make sure this is the version you want",
+                    release.version, installed
+                )
+                .as_str(),
+            );
+        }
+    }
+
+    let included_releases = releases_between(&fetch, &installed, release);
+    let breaking = show_release_notes(&spin, &included_releases);
+    confirm_update(&spin, &release.version, breaking, &args)?;
+
+    let arch = host_arch();
+
+    spin.set_message(format!("Downloading {} release", arch).as_str());
+
+    let asset = match release.asset_for(arch.as_str(), Some("linux")) {
+        Some(asset) => asset,
+        None => {
+            spin.fail("Failed to download release");
+            spin.finish();
+            return Err(RuntipiError::Other(format!(
+                "No asset found for {} {} on release {}",
+                arch, "linux", release.version
+            )));
+        }
+    };
+
+    let tmp_dir = tempfile::Builder::new().prefix("self_update").tempdir_in(&current_dir)?;
+    let tmp_tarball_path = tmp_dir.path().join(&asset.name);
+
+    match download(&asset.download_url, &tmp_tarball_path, &source) {
+        Ok(_) => {
+            spin.succeed(format!("Downloaded {}", &asset.name).as_str());
+        }
         Err(e) => {
-            spin.fail("Failed to start new CLI");
-            println!("\nDebug: {}", e);
-            return;
+            spin.fail("Failed to download release");
+            spin.finish();
+            return Err(e);
+        }
+    }
+
+    spin.set_message("Verifying release checksum");
+
+    if let Err(e) = download_checksums(&release.assets, &asset.name, tmp_dir.path(), &source) {
+        spin.fail("Failed to download release checksums");
+        spin.finish();
+        return Err(e);
+    }
+
+    verify_release(&spin, tmp_dir.path(), &asset.name, args.skip_verification)?;
+
+    spin.set_message("Extracting tarball");
+
+    let extract_dir = tmp_dir.path().join("extracted");
+    std::fs::create_dir(&extract_dir)?;
+
+    let new_executable_path = match release::extract_binary(&tmp_tarball_path, &asset.name, &arch, &extract_dir) {
+        Ok(path) => {
+            spin.succeed("Extracted tarball");
+            path
+        }
+        Err(e) => {
+            spin.fail("Failed to extract tarball");
+            spin.finish();
+            return Err(RuntipiError::Integrity(e));
+        }
+    };
+
+    install_and_start(&spin, &current_dir, &new_executable_path, &release.version, &args, false)
+}
+
+pub fn rollback(args: RollbackArgs) -> Result<(), RuntipiError> {
+    let spin = spinner::new("");
+    let root_folder = current_dir()?;
+
+    spin.set_message("Looking for rollback snapshots");
+
+    let snapshots = rollback::list_snapshots(&root_folder)?;
+    let found = match &args.version {
+        Some(version) => snapshots
+            .into_iter()
+            .find(|(snapshot_version, _)| snapshot_version.trim_start_matches('v') == version.trim_start_matches('v')),
+        None => snapshots.into_iter().next(),
+    };
+
+    let (version, snapshot) = match found {
+        Some(found) => {
+            spin.succeed(format!("Found snapshot of {}", found.0).as_str());
+            found
+        }
+        None => {
+            spin.fail("No rollback snapshot found");
+            spin.finish();
+            let message = match args.version {
+                Some(version) => format!("There is no rollback snapshot of version {}", version),
+                None => "There is no rollback snapshot. Snapshots are created when running `runtipi-cli update`".to_string(),
+            };
+            return Err(RuntipiError::InvalidInput(message));
         }
+    };
+
+    spin.suspend(stop::run)?;
+
+    spin.set_message(format!("Restoring {}", version).as_str());
+
+    if let Err(e) = restore_snapshot(&root_folder, &snapshot) {
+        spin.fail("Failed to restore snapshot");
+        spin.finish();
+        return Err(e);
     }
 
+    spin.succeed(format!("Restored CLI and files of {}", version).as_str());
+    spin.set_message("Starting Tipi... This may take a while.");
+
+    if let Err(e) = start_cli(&args.env_file, args.no_permissions, false) {
+        spin.fail("Failed to start tipi");
+        spin.finish();
+        return Err(e);
+    }
+
+    spin.succeed("Tipi started");
     spin.finish();
 
-    println!("\n");
+    output::data("version", &version);
 
-    let ip_and_port = format!(
-        "Visit http://{}:{} to access the dashboard",
-        env_map.get("INTERNAL_IP").unwrap(),
-        env_map.get("NGINX_PORT").unwrap()
+    let console_box = ConsoleBox::new(
+        "Runtipi rolled back successfully".to_string(),
+        format!("You are now running version {}", version),
+        80,
+        "green".to_string(),
     );
+    console_box.print();
 
-    let box_title = "Runtipi started successfully".to_string();
-    let box_body = format!(
-        "{}\n\n{}\n\n{}",
-        ip_and_port,
-        format!("You are now running version {}", release.version),
-        "Tipi is entirely written in TypeScript and we are looking for contributors!"
-    );
+    Ok(())
+}
 
-    let console_box = ConsoleBox::new(box_title, box_body, 80, "green".to_string());
-    console_box.print();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn releases(versions: &[&str]) -> Vec<Release> {
+        versions
+            .iter()
+            .map(|version| Release {
+                name: version.to_string(),
+                version: version.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn version(version: &str) -> Version {
+        release::parse_version(version).unwrap()
+    }
+
+    fn versions_between(releases: &[Release], installed: &str, target: &str) -> Vec<String> {
+        let target = releases.iter().find(|r| r.version == target).unwrap();
+        releases_between(releases, installed, target)
+            .into_iter()
+            .map(|r| r.version.clone())
+            .collect()
+    }
+
+    fn check(current: &str, target: &str, allow_downgrade: bool) -> Result<(), String> {
+        let releases = releases(&["v0.9.0", "v0.10.0", "v1.0.0-beta.2", "v1.0.0", "v1.2.0", "v2.0.0", "v2.1.0"]);
+        check_version_change(&releases, &version(current), &version(target), allow_downgrade).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn allows_updates_within_a_major_version() {
+        assert_eq!(check("v0.9.0", "v0.10.0", false), Ok(()));
+        assert_eq!(check("v1.0.0-beta.2", "v1.0.0", false), Ok(()));
+        assert_eq!(check("v1.0.0", "v1.2.0", false), Ok(()));
+        assert_eq!(check("v1.2.0", "v1.2.0", false), Ok(()));
+    }
+
+    #[test]
+    fn refuses_downgrades_unless_allowed() {
+        let error = check("v0.10.0", "v0.9.0", false).unwrap_err();
+        assert!(error.contains("v0.9.0 is older than the installed version v0.10.0"), "{}", error);
+        assert!(error.contains("--allow-downgrade"), "{}", error);
+
+        assert_eq!(check("v0.10.0", "v0.9.0", true), Ok(()));
+        assert_eq!(check("v1.0.0", "v1.0.0-beta.2", true), Ok(()));
+        assert!(check("v1.0.0", "v1.0.0-beta.2", false).is_err());
+    }
+
+    #[test]
+    fn refuses_major_downgrades_even_when_allowed() {
+        for allow_downgrade in [false, true] {
+            let error = check("v2.0.0", "v1.2.0", allow_downgrade).unwrap_err();
+            assert!(error.starts_with("Downgrading from v2.0.0 to another major version"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn lists_the_breaking_updates_of_a_major_update() {
+        let error = check("v0.10.0", "v2.1.0", false).unwrap_err();
+        assert!(
+            error.contains("Breaking updates to go through from v0.10.0: v1.0.0 -> v2.0.0"),
+            "{}",
+            error
+        );
+
+        let releases = releases(&["v3.0.0-rc.1", "v3.0.2", "v3.0.1"]);
+        assert_eq!(
+            breaking_updates(&releases, &version("v2.1.0"), &version("v4.1.0")),
+            [version("v3.0.1"), version("v4.0.0")]
+        );
+    }
+
+    #[test]
+    fn lists_the_releases_between_two_versions() {
+        let releases = releases(&["v0.9.0", "v0.10.0", "v0.10.1-beta.1", "v0.10.1", "v0.11.0-beta.1", "nightly"]);
+
+        assert_eq!(versions_between(&releases, "v0.9.0", "v0.10.1"), ["v0.10.1", "v0.10.0"]);
+        assert_eq!(
+            versions_between(&releases, "v0.10.0", "v0.11.0-beta.1"),
+            ["v0.11.0-beta.1", "v0.10.1", "v0.10.1-beta.1"]
+        );
+        assert_eq!(versions_between(&releases, "v0.10.1", "v0.10.1"), Vec::<String>::new());
+        assert_eq!(versions_between(&releases, "v0.10.1", "nightly"), ["nightly"]);
+        assert_eq!(versions_between(&releases, "nightly", "v0.10.0"), ["v0.10.0"]);
+    }
 }
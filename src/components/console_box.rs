@@ -1,4 +1,6 @@
 use colored::Colorize;
+
+use super::output;
 // Example of the Box component printed in the console:
 //
 // ╔════════════════════════ Tipi successfully started 🎉 ════════════════════════╗
@@ -45,6 +47,10 @@ impl ConsoleBox {
     }
 
     pub fn print(&self) {
+        if output::is_json() {
+            return;
+        }
+
         // Find the longest line and set the box width
         let box_width = self.width;
 
@@ -75,7 +81,7 @@ impl ConsoleBox {
                         current_line = String::new();
                     }
                     current_line.push_str(word);
-                    current_line.push_str(" ");
+                    current_line.push(' ');
                 }
                 split_lines.push(current_line);
                 for line in split_lines {
@@ -0,0 +1,157 @@
+use colored::{ColoredString, Colorize};
+
+// Renders the subset of markdown used in release notes for the terminal:
+//
+// ## Features             -> bold heading
+// - **app**: new feature  -> • app: new feature
+// `code`, [text](url)     -> colored code, text (url)
+//
+// Lines mentioning a breaking change are highlighted in red
+
+/**
+* Render inline markdown: **bold**, __bold__, `code` and [links](url)
+*/
+fn render_inline(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let marker = ["**", "__"].into_iter().find(|marker| rest.starts_with(marker));
+
+        if let Some(marker) = marker {
+            if let Some(end) = rest[2..].find(marker) {
+                result.push_str(&rest[2..2 + end].bold().to_string());
+                rest = &rest[end + 4..];
+                continue;
+            }
+        }
+
+        if let Some(code) = rest.strip_prefix('`') {
+            if let Some(end) = code.find('`') {
+                result.push_str(&code[..end].cyan().to_string());
+                rest = &code[end + 1..];
+                continue;
+            }
+        }
+
+        if let Some(link) = rest.strip_prefix('[') {
+            if let Some((label, after)) = link.split_once("](") {
+                if let Some(end) = after.find(')') {
+                    result.push_str(&format!("{} ({})", label, after[..end].dimmed()));
+                    rest = &after[end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        let next = rest.chars().next().unwrap_or_default();
+        result.push(next);
+        rest = &rest[next.len_utf8()..];
+    }
+
+    result
+}
+
+pub fn is_breaking(line: &str) -> bool {
+    line.to_lowercase().contains("breaking")
+}
+
+fn highlight(line: String, breaking: bool) -> ColoredString {
+    if breaking {
+        line.red().bold()
+    } else {
+        line.normal()
+    }
+}
+
+pub fn render(markdown: &str) -> String {
+    let mut lines = vec![];
+    let mut in_code_block = false;
+    let mut in_comment = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+
+        if in_code_block {
+            lines.push(format!("    {}", line.dimmed()));
+            continue;
+        }
+
+        // Release notes often contain html comments left by the release template
+        if in_comment || trimmed.starts_with("<!--") {
+            in_comment = !trimmed.ends_with("-->");
+            continue;
+        }
+
+        let breaking = is_breaking(trimmed);
+        let indent = &line[..line.len() - line.trim_start().len()];
+
+        let rendered = if let Some(heading) = trimmed.strip_prefix("# ") {
+            highlight(render_inline(heading), breaking).bold().underline().to_string()
+        } else if trimmed.starts_with('#') {
+            let heading = trimmed.trim_start_matches('#').trim();
+            highlight(render_inline(heading), breaking).bold().to_string()
+        } else if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|bullet| trimmed.strip_prefix(bullet)) {
+            format!("{}• {}", indent, highlight(render_inline(item), breaking))
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            format!("{} {}", "│".dimmed(), highlight(render_inline(quote.trim()), breaking).italic())
+        } else if trimmed.len() >= 3 && trimmed.chars().all(|c| c == '-' || c == '*' || c == '_') {
+            "─".repeat(40).dimmed().to_string()
+        } else {
+            format!("{}{}", indent, highlight(render_inline(trimmed), breaking))
+        };
+
+        lines.push(rendered);
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(markdown: &str) -> String {
+        colored::control::set_override(false);
+        render(markdown)
+    }
+
+    #[test]
+    fn inline_markdown() {
+        assert_eq!(
+            plain("**app**: __new__ `runtipi-cli update` see [the docs](https://runtipi.io)"),
+            "app: new runtipi-cli update see the docs (https://runtipi.io)"
+        );
+        assert_eq!(plain("unclosed **bold and `code and [link]"), "unclosed **bold and `code and [link]");
+        assert_eq!(plain("émojis 🎉 **ok**"), "émojis 🎉 ok");
+    }
+
+    #[test]
+    fn blocks() {
+        let markdown = "# v3.1.0\n## Features\n- **app**: new feature\n  * nested\n> quoted\n---\nText";
+
+        assert_eq!(
+            plain(markdown),
+            "v3.1.0\nFeatures\n• app: new feature\n  • nested\n│ quoted\n────────────────────────────────────────\nText"
+        );
+    }
+
+    #[test]
+    fn code_blocks_and_comments() {
+        let markdown = "<!-- Release template\n- not rendered -->\nBefore\n```bash\n# not a heading\n- not a bullet\n```\nAfter";
+
+        assert_eq!(plain(markdown), "Before\n    # not a heading\n    - not a bullet\nAfter");
+    }
+
+    #[test]
+    fn breaking_changes() {
+        assert!(is_breaking("- **Breaking**: settings.json moved"));
+        assert!(is_breaking("## BREAKING CHANGES"));
+        assert!(!is_breaking("- fix: break loop"));
+    }
+}
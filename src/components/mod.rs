@@ -1,2 +1,5 @@
 pub mod console_box;
+pub mod markdown;
+pub mod output;
+pub mod prompt;
 pub mod spinner;
@@ -0,0 +1,135 @@
+use std::sync::{Mutex, OnceLock};
+
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// In text mode, everything is printed as it happens. In json mode, spinners and boxes are hidden and
+// every step, message and error is collected into a single report printed when the command is done.
+//
+// Example of a report:
+//
+// {
+//   "command": "start",
+//   "success": true,
+//   "steps": [{ "message": "User permissions are ok", "success": true }, ...],
+//   "messages": [],
+//   "errors": [],
+//   "data": { "dashboard_url": "http://10.0.3.152:80" }
+// }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct Step {
+    message: String,
+    success: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    command: String,
+    success: bool,
+    steps: Vec<Step>,
+    messages: Vec<String>,
+    errors: Vec<String>,
+    data: Map<String, Value>,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+pub fn init(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+
+    if format == OutputFormat::Json {
+        colored::control::set_override(false);
+    }
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+fn with_report(f: impl FnOnce(&mut Report)) {
+    let mut report = REPORT.lock().unwrap_or_else(|e| e.into_inner());
+    f(report.get_or_insert_with(Report::default));
+}
+
+pub fn step(message: &str, success: bool) {
+    with_report(|report| {
+        report.steps.push(Step {
+            message: message.to_string(),
+            success,
+        })
+    });
+}
+
+/**
+* Outcome of a step that doesn't go through a spinner
+*/
+pub fn success(message: &str) {
+    step(message, true);
+    if !is_json() {
+        println!("{} {}", "✓".green(), message);
+    }
+}
+
+pub fn failure(message: &str) {
+    step(message, false);
+    if !is_json() {
+        println!("{} {}", "✗".red(), message);
+    }
+}
+
+/**
+* Informational message for the user
+*/
+pub fn info(message: &str) {
+    if is_json() {
+        with_report(|report| report.messages.push(message.to_string()));
+    } else {
+        println!("{}", message);
+    }
+}
+
+pub fn error(message: &str) {
+    if is_json() {
+        with_report(|report| report.errors.push(message.to_string()));
+    } else {
+        println!("\nError: {}", message);
+    }
+}
+
+/**
+* Structured result of the command. Only part of the json report, text mode prints its own version
+*/
+pub fn data(key: &str, value: impl Serialize) {
+    if is_json() {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        with_report(|report| {
+            report.data.insert(key.to_string(), value);
+        });
+    }
+}
+
+/**
+* Print the json report, if enabled. Must be called once, when the command is done
+*/
+pub fn finish(command: &str) {
+    if !is_json() {
+        return;
+    }
+
+    let mut report = REPORT.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default();
+    report.command = command.to_string();
+    report.success = report.errors.is_empty() && report.steps.iter().all(|s| s.success);
+
+    println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+}
@@ -0,0 +1,23 @@
+use std::io::{stdin, stdout, Error, IsTerminal, Write};
+
+use super::output;
+
+/**
+* Whether the user can answer a prompt. Json output and scripts must pass the answer as a flag instead
+*/
+pub fn can_prompt() -> bool {
+    !output::is_json() && stdin().is_terminal()
+}
+
+/**
+* Ask a yes or no question, no being the default
+*/
+pub fn confirm(question: &str) -> Result<bool, Error> {
+    print!("{} [y/N] ", question);
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -2,20 +2,29 @@ use std::time::Duration;
 
 use indicatif::{ProgressBar, ProgressStyle};
 
+use super::output;
+
 pub struct CustomSpinner {
     spinner: ProgressBar,
 }
 
 impl CustomSpinner {
     fn new(initial_message: String) -> CustomSpinner {
-        let spinner = ProgressBar::new_spinner();
+        // Spinners are hidden in json mode, only the outcome of each step is reported
+        let spinner = if output::is_json() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new_spinner()
+        };
         let style: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
         let progress_style = ProgressStyle::default_spinner();
 
         spinner.set_style(ProgressStyle::tick_strings(progress_style, style));
 
         spinner.set_message(initial_message);
-        spinner.enable_steady_tick(Duration::from_millis(50));
+        if !output::is_json() {
+            spinner.enable_steady_tick(Duration::from_millis(50));
+        }
 
         CustomSpinner { spinner }
     }
@@ -23,6 +32,11 @@ impl CustomSpinner {
     pub fn succeed(&self, message: &str) {
         const CHECKMARK: &str = "\u{001b}[32;1m\u{2713}\u{001b}[0m";
 
+        output::step(message, true);
+        if output::is_json() {
+            return;
+        }
+
         let success_message = format!("{} {}", CHECKMARK, message);
 
         self.spinner.println(success_message);
@@ -31,6 +45,11 @@ impl CustomSpinner {
     pub fn fail(&self, message: &str) {
         const CROSSMARK: &str = "\u{001b}[31;1m\u{2717}\u{001b}[0m";
 
+        output::step(message, false);
+        if output::is_json() {
+            return;
+        }
+
         let failure_message = format!("{} {}", CROSSMARK, message);
 
         self.spinner.println(failure_message);
@@ -48,13 +67,18 @@ impl CustomSpinner {
         self.spinner.set_message(message.to_string());
     }
 
+    /**
+     * Hide the spinner while running `f`, eg: to prompt the user
+     */
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.spinner.suspend(f)
+    }
+
     pub fn finish(&self) {
         self.spinner.finish_and_clear();
     }
 }
 
 pub fn new(message: &str) -> CustomSpinner {
-    let spinner = CustomSpinner::new(message.to_string());
-
-    return spinner;
+    CustomSpinner::new(message.to_string())
 }
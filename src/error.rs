@@ -0,0 +1,90 @@
+use std::fmt;
+
+use crate::utils::worker_api::WorkerApiError;
+
+/// Error returned by every command. Each category maps to a distinct process exit code
+/// so that scripts and service managers can tell failures apart:
+///
+/// | Code | Category                                            |
+/// |------|-----------------------------------------------------|
+/// | 0    | Success                                             |
+/// | 1    | Unexpected error (I/O...)                           |
+/// | 2    | Invalid arguments or input                          |
+/// | 3    | Docker is not installed or not accessible           |
+/// | 4    | A docker compose operation failed                   |
+/// | 5    | The .env file could not be generated                |
+/// | 6    | Network error (GitHub, downloads...)                |
+/// | 7    | Insufficient file permissions                       |
+/// | 8    | The worker API is unreachable or returned an error  |
+/// | 9    | Tipi is running but some components are unhealthy   |
+/// | 10   | Tipi is down                                        |
+/// | 11   | A download failed its checksum or signature check   |
+#[derive(Debug)]
+pub enum RuntipiError {
+    Docker(String),
+    Compose(String),
+    Env(String),
+    Network(String),
+    Permissions(String),
+    Api(WorkerApiError),
+    InvalidInput(String),
+    Degraded(String),
+    Down(String),
+    Integrity(String),
+    Io(std::io::Error),
+    Other(String),
+}
+
+impl RuntipiError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RuntipiError::Io(_) | RuntipiError::Other(_) => 1,
+            RuntipiError::InvalidInput(_) => 2,
+            RuntipiError::Docker(_) => 3,
+            RuntipiError::Compose(_) => 4,
+            RuntipiError::Env(_) => 5,
+            RuntipiError::Network(_) => 6,
+            RuntipiError::Permissions(_) => 7,
+            RuntipiError::Api(_) => 8,
+            RuntipiError::Degraded(_) => 9,
+            RuntipiError::Down(_) => 10,
+            RuntipiError::Integrity(_) => 11,
+        }
+    }
+}
+
+impl fmt::Display for RuntipiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntipiError::Docker(message)
+            | RuntipiError::Compose(message)
+            | RuntipiError::Env(message)
+            | RuntipiError::Network(message)
+            | RuntipiError::Permissions(message)
+            | RuntipiError::InvalidInput(message)
+            | RuntipiError::Degraded(message)
+            | RuntipiError::Down(message)
+            | RuntipiError::Integrity(message)
+            | RuntipiError::Other(message) => write!(f, "{}", message.trim()),
+            RuntipiError::Api(err) => write!(f, "{}", err),
+            RuntipiError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RuntipiError {}
+
+impl From<std::io::Error> for RuntipiError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => RuntipiError::Permissions(err.to_string()),
+            _ => RuntipiError::Io(err),
+        }
+    }
+}
+
+impl From<WorkerApiError> for RuntipiError {
+    fn from(err: WorkerApiError) -> Self {
+        RuntipiError::Api(err)
+    }
+}
@@ -1,48 +1,63 @@
 mod args;
 mod commands;
 mod components;
+mod error;
 mod utils;
 
-use args::RuntipiArgs;
+use args::{RuntipiArgs, UpdateSubcommand, VersionEnum};
 use clap::Parser;
 use colored::Colorize;
 
 use crate::commands::update::UpdateArgs;
+use crate::components::output;
 
 fn main() {
     let args = RuntipiArgs::parse();
 
-    println!("{}", "Welcome to Runtipi CLI ✨\n".green());
-
-    match args.command {
-        args::RuntipiMainCommand::Start(args) => {
-            commands::start::run(args);
-        }
-        args::RuntipiMainCommand::Stop => {
-            commands::stop::run();
-        }
-        args::RuntipiMainCommand::Restart(args) => {
-            commands::stop::run();
-            commands::start::run(args);
-        }
-        args::RuntipiMainCommand::Update(update_command) => {
-            let args = UpdateArgs {
-                version: update_command.version.to_string(),
-                env_file: update_command.env_file,
-                no_permissions: update_command.no_permissions,
-            };
-
-            commands::stop::run();
-            commands::update::run(args);
-        }
-        args::RuntipiMainCommand::ResetPassword => {
-            commands::reset_password::run();
-        }
-        args::RuntipiMainCommand::App(app_command) => {
-            commands::app::run(app_command);
-        }
-        args::RuntipiMainCommand::Debug => {
-            commands::debug::run();
-        }
+    output::init(args.output);
+
+    if !output::is_json() {
+        println!("{}", "Welcome to Runtipi CLI ✨\n".green());
+    }
+
+    let (command, result) = match args.command {
+        args::RuntipiMainCommand::Start(args) => ("start", commands::start::run(args)),
+        args::RuntipiMainCommand::Stop => ("stop", commands::stop::run()),
+        args::RuntipiMainCommand::Restart(args) => ("restart", commands::stop::run().and_then(|_| commands::start::run(args))),
+        args::RuntipiMainCommand::Update(update_command) => match (update_command.subcommand, update_command.version) {
+            (Some(UpdateSubcommand::Rollback(args)), _) => ("update", commands::update::rollback(args)),
+            (None, _) if update_command.check => ("update", commands::update::check(update_command.channel)),
+            (None, version) => {
+                let args = UpdateArgs {
+                    version: version.unwrap_or(VersionEnum::Latest),
+                    env_file: update_command.env_file,
+                    no_permissions: update_command.no_permissions,
+                    skip_verification: update_command.skip_verification,
+                    allow_downgrade: update_command.allow_downgrade,
+                    channel: update_command.channel,
+                    from_file: update_command.from_file,
+                    yes: update_command.yes,
+                };
+
+                ("update", commands::update::run(args))
+            }
+        },
+        args::RuntipiMainCommand::ResetPassword => ("reset-password", commands::reset_password::run()),
+        args::RuntipiMainCommand::App(app_command) => ("app", commands::app::run(app_command)),
+        args::RuntipiMainCommand::Debug => ("debug", commands::debug::run()),
+        args::RuntipiMainCommand::Status => ("status", commands::status::run()),
+        args::RuntipiMainCommand::Backup(backup_command) => ("backup", commands::backup::run(backup_command)),
+        args::RuntipiMainCommand::ExportImages(args) => ("export-images", commands::export_images::run(args)),
+        args::RuntipiMainCommand::Config(config_command) => ("config", commands::config::run(config_command)),
+    };
+
+    if let Err(e) = &result {
+        output::error(&e.to_string());
+    }
+
+    output::finish(command);
+
+    if let Err(e) = result {
+        std::process::exit(e.exit_code());
     }
 }
@@ -0,0 +1,356 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use reqwest::Url;
+use serde_json::{Map, Number, Value};
+
+use super::schemas::FormField;
+
+// Fields of the install form that are not declared in the app's config.json
+const BUILTIN_FIELDS: [(&str, &str); 4] = [
+    ("exposed", "boolean"),
+    ("domain", "fqdn"),
+    ("openPort", "boolean"),
+    ("isVisibleOnGuestDashboard", "boolean"),
+];
+
+/**
+* Parse `KEY=VALUE` pairs given on the command line
+*/
+pub fn parse_set_values(values: &[String]) -> Result<Map<String, Value>, String> {
+    let mut map = Map::new();
+
+    for value in values {
+        match value.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                map.insert(key.trim().to_string(), Value::String(value.to_string()));
+            }
+            _ => return Err(format!("Invalid value '{}'. Expected KEY=VALUE", value)),
+        }
+    }
+
+    Ok(map)
+}
+
+/**
+* Read form values from a JSON file containing a single object
+*/
+pub fn read_values_file(path: &Path) -> Result<Map<String, Value>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+
+    match serde_json::from_str(&content) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(format!("{} must contain a JSON object", path.display())),
+        Err(e) => Err(format!("Unable to parse {}: {}", path.display(), e)),
+    }
+}
+
+pub fn is_valid_fqdn(value: &str) -> bool {
+    let value = value.strip_suffix('.').unwrap_or(value);
+
+    !value.is_empty()
+        && value.len() <= 253
+        && value.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn coerce(field_type: &str, value: &Value) -> Result<Value, String> {
+    match field_type {
+        "number" => match value {
+            Value::Number(_) => Ok(value.clone()),
+            Value::String(s) => {
+                if let Ok(i) = s.trim().parse::<i64>() {
+                    Ok(Value::Number(i.into()))
+                } else {
+                    s.trim()
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(Number::from_f64)
+                        .map(Value::Number)
+                        .ok_or(format!("'{}' is not a number", s))
+                }
+            }
+            _ => Err(format!("{} is not a number", value)),
+        },
+        "boolean" => match value {
+            Value::Bool(_) => Ok(value.clone()),
+            Value::String(s) => match s.trim().to_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" => Ok(Value::Bool(false)),
+                _ => Err(format!("'{}' is not a boolean", s)),
+            },
+            _ => Err(format!("{} is not a boolean", value)),
+        },
+        _ => {
+            let s = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return Err(format!("{} is not a string", value)),
+            };
+
+            let valid = match field_type {
+                "email" => s.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.')),
+                "url" => Url::parse(&s).is_ok(),
+                "ip" => s.parse::<IpAddr>().is_ok(),
+                "fqdn" => is_valid_fqdn(&s),
+                "fqdnip" => is_valid_fqdn(&s) || s.parse::<IpAddr>().is_ok(),
+                _ => true,
+            };
+
+            if valid {
+                Ok(Value::String(s))
+            } else {
+                Err(format!("'{}' is not a valid {}", s, field_type))
+            }
+        }
+    }
+}
+
+fn check_bounds(field: &FormField, value: &Value) -> Result<(), String> {
+    let (measure, unit) = match value {
+        Value::Number(n) => (n.as_f64().unwrap_or_default(), ""),
+        Value::String(s) => (s.chars().count() as f64, " characters"),
+        _ => return Ok(()),
+    };
+
+    if let Some(min) = field.min {
+        if measure < min {
+            return Err(format!("must be at least {}{}", min, unit));
+        }
+    }
+
+    if let Some(max) = field.max {
+        if measure > max {
+            return Err(format!("must be at most {}{}", max, unit));
+        }
+    }
+
+    Ok(())
+}
+
+/**
+* Validate the provided values against the app's form fields and build the form sent to the worker.
+* Every problem is reported at once so that the user can fix them in a single pass
+*/
+pub fn build_form(fields: &[FormField], values: &Map<String, Value>) -> Result<Map<String, Value>, Vec<String>> {
+    let mut form = Map::new();
+    let mut errors = vec![];
+
+    for (key, value) in values {
+        let field = fields.iter().find(|f| &f.env_variable == key);
+        let field_type = match field {
+            Some(field) => field.field_type.as_str(),
+            None => match BUILTIN_FIELDS.iter().find(|(name, _)| name == key) {
+                Some((_, field_type)) => field_type,
+                None => {
+                    errors.push(format!("{}: unknown field", key));
+                    continue;
+                }
+            },
+        };
+
+        let value = match coerce(field_type, value) {
+            Ok(value) => value,
+            Err(e) => {
+                errors.push(format!("{}: {}", key, e));
+                continue;
+            }
+        };
+
+        if let Some(field) = field {
+            if let Err(e) = check_bounds(field, &value) {
+                errors.push(format!("{}: {}", key, e));
+                continue;
+            }
+
+            if !field.options.is_empty() && !field.options.iter().any(|o| Value::String(o.value.clone()) == value) {
+                let options = field.options.iter().map(|o| o.value.as_str()).collect::<Vec<&str>>();
+                errors.push(format!("{}: must be one of {}", key, options.join(", ")));
+                continue;
+            }
+        }
+
+        form.insert(key.clone(), value);
+    }
+
+    for field in fields {
+        if form.contains_key(&field.env_variable) || values.contains_key(&field.env_variable) {
+            continue;
+        }
+
+        // Random fields are generated by the worker
+        if field.field_type == "random" {
+            continue;
+        }
+
+        match &field.default {
+            Some(default) => {
+                form.insert(field.env_variable.clone(), default.clone());
+            }
+            None if field.required => {
+                let label = field.label.as_deref().unwrap_or(&field.env_variable);
+                errors.push(format!("{}: required field '{}' is missing", field.env_variable, label));
+            }
+            None => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(form)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(value: Value) -> Vec<FormField> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn values(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("values must be an object"),
+        }
+    }
+
+    #[test]
+    fn set_values() {
+        let map = parse_set_values(&["A=1".to_string(), " B =x=y".to_string(), "C=".to_string()]).unwrap();
+
+        assert_eq!(map["A"], "1");
+        assert_eq!(map["B"], "x=y");
+        assert_eq!(map["C"], "");
+        assert!(parse_set_values(&["novalue".to_string()]).is_err());
+        assert!(parse_set_values(&["=1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(coerce("number", &json!("42")), Ok(json!(42)));
+        assert_eq!(coerce("number", &json!(" 1.5 ")), Ok(json!(1.5)));
+        assert_eq!(coerce("number", &json!(7)), Ok(json!(7)));
+        assert!(coerce("number", &json!("abc")).is_err());
+        assert!(coerce("number", &json!(true)).is_err());
+    }
+
+    #[test]
+    fn booleans() {
+        assert_eq!(coerce("boolean", &json!("yes")), Ok(json!(true)));
+        assert_eq!(coerce("boolean", &json!("FALSE")), Ok(json!(false)));
+        assert_eq!(coerce("boolean", &json!("1")), Ok(json!(true)));
+        assert_eq!(coerce("boolean", &json!(false)), Ok(json!(false)));
+        assert!(coerce("boolean", &json!("maybe")).is_err());
+        assert!(coerce("boolean", &json!(1)).is_err());
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(coerce("text", &json!(12)), Ok(json!("12")));
+        assert_eq!(coerce("password", &json!(true)), Ok(json!("true")));
+        assert!(coerce("text", &json!(["a"])).is_err());
+
+        assert!(coerce("email", &json!("me@example.com")).is_ok());
+        assert!(coerce("email", &json!("me@localhost")).is_err());
+        assert!(coerce("email", &json!("@example.com")).is_err());
+
+        assert!(coerce("url", &json!("https://example.com/path")).is_ok());
+        assert!(coerce("url", &json!("example.com")).is_err());
+
+        assert!(coerce("ip", &json!("192.168.1.1")).is_ok());
+        assert!(coerce("ip", &json!("::1")).is_ok());
+        assert!(coerce("ip", &json!("300.1.1.1")).is_err());
+
+        assert!(coerce("fqdn", &json!("app.example.com")).is_ok());
+        assert!(coerce("fqdn", &json!("-bad.example.com")).is_err());
+        assert!(coerce("fqdn", &json!("under_score.com")).is_err());
+
+        assert!(coerce("fqdnip", &json!("10.0.0.1")).is_ok());
+        assert!(coerce("fqdnip", &json!("host.local")).is_ok());
+        assert!(coerce("fqdnip", &json!("not valid")).is_err());
+    }
+
+    #[test]
+    fn bounds() {
+        let fields = fields(json!([
+            { "type": "number", "env_variable": "PORT", "min": 1, "max": 65535 },
+            { "type": "password", "env_variable": "PASSWORD", "min": 8, "max": 12 }
+        ]));
+
+        assert!(build_form(&fields, &values(json!({ "PORT": "8080", "PASSWORD": "12345678" }))).is_ok());
+        assert!(build_form(&fields, &values(json!({ "PORT": 65535, "PASSWORD": "123456789012" }))).is_ok());
+
+        let errors = build_form(&fields, &values(json!({ "PORT": "0", "PASSWORD": "short" }))).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "PORT: must be at least 1".to_string(),
+                "PASSWORD: must be at least 8 characters".to_string()
+            ]
+        );
+
+        let errors = build_form(&fields, &values(json!({ "PORT": 70000, "PASSWORD": "1234567890123" }))).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "PORT: must be at most 65535".to_string(),
+                "PASSWORD: must be at most 12 characters".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn options() {
+        let fields = fields(json!([
+            { "type": "text", "env_variable": "MODE", "options": [{ "value": "fast" }, { "value": "slow" }] }
+        ]));
+
+        assert_eq!(build_form(&fields, &values(json!({ "MODE": "slow" }))).unwrap()["MODE"], "slow");
+        assert_eq!(
+            build_form(&fields, &values(json!({ "MODE": "medium" }))).unwrap_err(),
+            vec!["MODE: must be one of fast, slow".to_string()]
+        );
+    }
+
+    #[test]
+    fn defaults_required_and_unknown_fields() {
+        let fields = fields(json!([
+            { "type": "text", "env_variable": "NAME", "label": "Name", "required": true },
+            { "type": "number", "env_variable": "WORKERS", "default": 2 },
+            { "type": "random", "env_variable": "SECRET", "required": true },
+            { "type": "text", "env_variable": "OPTIONAL" }
+        ]));
+
+        let form = build_form(
+            &fields,
+            &values(json!({ "NAME": "tipi", "exposed": "true", "domain": "tipi.example.com" })),
+        )
+        .unwrap();
+        assert_eq!(form["NAME"], "tipi");
+        assert_eq!(form["WORKERS"], 2);
+        assert_eq!(form["exposed"], true);
+        assert_eq!(form["domain"], "tipi.example.com");
+        assert!(!form.contains_key("SECRET"));
+        assert!(!form.contains_key("OPTIONAL"));
+
+        let errors = build_form(&fields, &values(json!({ "WORKERS": "many", "OTHER": "x" }))).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "WORKERS: 'many' is not a number".to_string(),
+                "OTHER: unknown field".to_string(),
+                "NAME: required field 'Name' is missing".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,745 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::EntryType;
+
+// Example of a backup archive:
+//
+// runtipi-backup-2024-01-31_12-00-00.tar.gz
+// ├── .env
+// ├── state/...
+// ├── user-config/...
+// ├── app-data/<app-id>/...  <- from the storage folder, the root folder unless STORAGE_PATH is set
+// ├── apps/<app-id>/...
+// ├── traefik/...
+// ├── database/tipi.sql
+// └── manifest.json
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+pub const DATABASE_DUMP_NAME: &str = "database/tipi.sql";
+pub const MANIFEST_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub created_at: String,
+    pub tipi_version: String,
+    pub cli_version: String,
+    /// Id of the app for single app backups, None for a full instance backup
+    pub app_id: Option<String>,
+    pub includes_app_data: bool,
+    /// Apps included in the backup. None means every app
+    pub apps: Option<Vec<String>>,
+    /// Path of the postgres dump in the archive, if any
+    pub database_dump: Option<String>,
+    /// Sha256 checksum of every regular file in the archive, manifest excluded
+    pub checksums: BTreeMap<String, String>,
+}
+
+struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/**
+* Writes a gzipped tarball while computing the checksum of every file added to it
+*/
+pub struct ArchiveWriter {
+    builder: tar::Builder<GzEncoder<File>>,
+    checksums: BTreeMap<String, String>,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: &Path) -> Result<ArchiveWriter, Error> {
+        // Archives contain the secrets of the .env file and the database, only the owner can read them
+        let file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        builder.follow_symlinks(false);
+
+        Ok(ArchiveWriter {
+            builder,
+            checksums: BTreeMap::new(),
+        })
+    }
+
+    pub fn add_file(&mut self, source: &Path, name: &str) -> Result<(), Error> {
+        let metadata = fs::symlink_metadata(source)?;
+
+        if !metadata.is_file() {
+            return self.builder.append_path_with_name(source, name);
+        }
+
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        let mut reader = HashingReader {
+            inner: File::open(source)?,
+            hasher: Sha256::new(),
+        };
+        self.builder.append_data(&mut header, name, &mut reader)?;
+        self.checksums.insert(name.to_string(), hex::encode(reader.hasher.finalize()));
+
+        Ok(())
+    }
+
+    /**
+     * Recursively add a folder. `skip` is called with the path relative to the archive root and can exclude entries
+     */
+    pub fn add_dir(&mut self, source: &Path, name: &str, skip: &dyn Fn(&str) -> bool) -> Result<(), Error> {
+        if skip(name) {
+            return Ok(());
+        }
+
+        self.builder.append_dir(name, source)?;
+
+        let mut entries = fs::read_dir(source)?.collect::<Result<Vec<_>, Error>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let entry_name = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                self.add_dir(&entry.path(), &entry_name, skip)?;
+            } else if !skip(&entry_name) {
+                self.add_file(&entry.path(), &entry_name)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Append the manifest, with the checksums of every file added, and flush the archive to disk
+     */
+    pub fn finish(mut self, mut manifest: BackupManifest) -> Result<BackupManifest, Error> {
+        manifest.checksums = std::mem::take(&mut self.checksums);
+        let content = serde_json::to_vec_pretty(&manifest).map_err(Error::other)?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Utc::now().timestamp() as u64);
+        self.builder.append_data(&mut header, MANIFEST_NAME, content.as_slice())?;
+
+        self.builder.into_inner()?.finish()?;
+
+        Ok(manifest)
+    }
+}
+
+/**
+* Content of a backup archive whose checksums have been verified
+*/
+pub struct ArchiveContent {
+    pub manifest: BackupManifest,
+    /// Name of every entry in the archive, manifest excluded
+    pub entries: Vec<String>,
+}
+
+fn invalid_archive(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/**
+* Whether a relative path stays inside the folder it is relative to once `..` are resolved
+*/
+fn stays_inside(path: &Path) -> bool {
+    let mut depth = 0;
+
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/**
+* Read every entry of an archive, refusing unsafe paths and checking the checksums listed in its manifest.
+* Entries are extracted into `destination` when one is given
+*/
+fn read_archive(path: &Path, destination: Option<&Path>) -> Result<ArchiveContent, Error> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    archive.set_preserve_permissions(true);
+
+    let destination = match destination {
+        Some(destination) => Some(destination.canonicalize()?),
+        None => None,
+    };
+
+    let mut manifest: Option<BackupManifest> = None;
+    let mut checksums = BTreeMap::new();
+    let mut entries = vec![];
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_path_buf();
+        let name = entry_path.to_string_lossy().trim_end_matches('/').to_string();
+
+        if !entry_path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(invalid_archive(format!("Refusing to extract {} outside of the destination", name)));
+        }
+
+        if name == MANIFEST_NAME {
+            let parsed = serde_json::from_reader(&mut entry).map_err(|e| invalid_archive(format!("Invalid manifest: {}", e)))?;
+            manifest = Some(parsed);
+            continue;
+        }
+
+        if entry.header().entry_type().is_file() {
+            let mode = entry.header().mode()?;
+            let mut reader = HashingReader {
+                inner: &mut entry,
+                hasher: Sha256::new(),
+            };
+
+            match &destination {
+                Some(destination) => {
+                    let target = destination.join(&entry_path);
+                    let parent = target.parent().unwrap_or(destination);
+                    fs::create_dir_all(parent)?;
+
+                    // A symlink extracted earlier, as a parent or with the same name, could redirect the file outside of the destination
+                    let is_symlink = fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_symlink());
+                    if is_symlink || !parent.canonicalize()?.starts_with(destination) {
+                        return Err(invalid_archive(format!("Refusing to extract {} outside of the destination", name)));
+                    }
+
+                    let mut file = File::create(&target)?;
+                    std::io::copy(&mut reader, &mut file)?;
+                    file.set_permissions(fs::Permissions::from_mode(mode))?;
+                }
+                None => {
+                    std::io::copy(&mut reader, &mut std::io::sink())?;
+                }
+            }
+
+            checksums.insert(name.clone(), hex::encode(reader.hasher.finalize()));
+        } else if let Some(destination) = &destination {
+            if let Some(link) = entry.link_name()? {
+                // Hard links are relative to the archive root, symlinks to the folder of the entry
+                let base = match entry.header().entry_type() {
+                    EntryType::Link => Path::new(""),
+                    _ => entry_path.parent().unwrap_or(Path::new("")),
+                };
+
+                if !stays_inside(&base.join(&link)) {
+                    return Err(invalid_archive(format!(
+                        "Refusing to extract {} pointing to {} outside of the destination",
+                        name,
+                        link.display()
+                    )));
+                }
+            }
+
+            entry.unpack_in(destination)?;
+        }
+
+        entries.push(name);
+    }
+
+    let manifest = manifest.ok_or_else(|| invalid_archive(format!("{} is missing from the archive", MANIFEST_NAME)))?;
+
+    if manifest.format_version > MANIFEST_FORMAT_VERSION {
+        return Err(invalid_archive(format!(
+            "The archive was created by a newer version of the CLI ({}). Please update the CLI first",
+            manifest.cli_version
+        )));
+    }
+
+    for (name, expected) in &manifest.checksums {
+        match checksums.get(name) {
+            Some(actual) if actual == expected => {}
+            Some(_) => return Err(invalid_archive(format!("Checksum mismatch for {}", name))),
+            None => {
+                return Err(invalid_archive(format!(
+                    "{} is listed in the manifest but missing from the archive",
+                    name
+                )))
+            }
+        }
+    }
+
+    if let Some(name) = checksums.keys().find(|name| !manifest.checksums.contains_key(*name)) {
+        return Err(invalid_archive(format!("{} is not listed in the manifest", name)));
+    }
+
+    Ok(ArchiveContent { manifest, entries })
+}
+
+/**
+* Check the manifest and checksums of an archive without extracting it
+*/
+pub fn verify_archive(path: &Path) -> Result<ArchiveContent, Error> {
+    read_archive(path, None)
+}
+
+/**
+* Extract an archive into an existing folder, checking its manifest and checksums along the way
+*/
+pub fn extract_archive(path: &Path, destination: &Path) -> Result<ArchiveContent, Error> {
+    read_archive(path, Some(destination))
+}
+
+fn remove_path(path: &Path) -> Result<(), Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_path(source: &Path, destination: &Path) -> Result<(), Error> {
+    let metadata = fs::symlink_metadata(source)?;
+
+    if metadata.is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+    } else if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        fs::set_permissions(destination, metadata.permissions())
+    } else {
+        fs::copy(source, destination).map(|_| ())
+    }
+}
+
+/**
+* Move a file or folder, copying it when the destination is on another filesystem (eg: a STORAGE_PATH mount)
+*/
+fn move_path(source: &Path, destination: &Path) -> Result<(), Error> {
+    match fs::rename(source, destination) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            if let Err(e) = copy_path(source, destination) {
+                let _ = remove_path(destination);
+                return Err(e);
+            }
+
+            // The copy is in place, a source that can't be removed only wastes space in the staging folder
+            let _ = remove_path(source);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/**
+* Replace paths of the root folder by the ones extracted in the staging folder.
+* A path missing from the staging folder is removed from the root folder.
+* The current version of a path is kept aside and put back if the new one can't be moved in
+*/
+pub fn replace_paths(root_folder: &Path, staging: &Path, paths: &[String]) -> Result<(), Error> {
+    for path in paths {
+        let source = staging.join(path);
+        let destination = root_folder.join(path);
+
+        if fs::symlink_metadata(&source).is_err() {
+            remove_path(&destination)?;
+            continue;
+        }
+
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file_name = destination.file_name().unwrap_or_default().to_string_lossy();
+        let previous = destination.with_file_name(format!(".{}.previous", file_name));
+        remove_path(&previous)?;
+
+        let has_previous = match fs::rename(&destination, &previous) {
+            Ok(()) => true,
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+
+        if let Err(e) = move_path(&source, &destination) {
+            if has_previous {
+                let _ = fs::rename(&previous, &destination);
+            }
+            return Err(e);
+        }
+
+        if has_previous {
+            remove_path(&previous)?;
+        }
+    }
+
+    Ok(())
+}
+
+/**
+* Dump the tipi database from the running tipi-db container into a file
+*/
+pub fn dump_database(destination: &Path) -> Result<(), Error> {
+    let file = File::create(destination)?;
+
+    let output = Command::new("docker")
+        .arg("exec")
+        .arg("tipi-db")
+        .arg("pg_dump")
+        .arg("--username=tipi")
+        .arg("--dbname=tipi")
+        .arg("--clean")
+        .arg("--if-exists")
+        .stdout(file)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "pg_dump failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+fn psql(stdin: Stdio, args: &[&str]) -> Result<(), Error> {
+    let output = Command::new("docker")
+        .arg("exec")
+        .arg("--interactive")
+        .arg("tipi-db")
+        .arg("psql")
+        .arg("--username=tipi")
+        .arg("--dbname=tipi")
+        .arg("--quiet")
+        .arg("--set=ON_ERROR_STOP=1")
+        .args(args)
+        .stdin(stdin)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(format!("psql failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+
+    Ok(())
+}
+
+/**
+* Load a dump created by `dump_database` into the running tipi-db container
+*/
+pub fn load_database_dump(dump: &Path) -> Result<(), Error> {
+    psql(File::open(dump)?.into(), &[])
+}
+
+/**
+* Set the password of the tipi database user. The postgres data folder is not part of a backup so it
+* keeps the password it was initialized with, which may differ from the one of the restored .env file
+*/
+pub fn set_database_password(password: &str) -> Result<(), Error> {
+    let statement = format!("ALTER USER tipi WITH PASSWORD '{}'", password.replace('\'', "''"));
+    psql(Stdio::null(), &["--command", &statement])
+}
+
+/**
+* Pause or unpause a list of containers. Returns the containers that could not be changed
+*/
+pub fn set_paused(containers: &[String], paused: bool) -> Vec<String> {
+    let action = if paused { "pause" } else { "unpause" };
+
+    containers
+        .iter()
+        .filter(|name| {
+            let result = Command::new("docker").arg(action).arg(name).output();
+            !result.is_ok_and(|output| output.status.success())
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn default_backup_folder(root_folder: &Path) -> PathBuf {
+    root_folder.join("backups")
+}
+
+/**
+* Name of a new backup archive eg: runtipi-backup-2024-01-31_12-00-00.tar.gz
+*/
+pub fn archive_name(prefix: &str) -> String {
+    format!("{}-{}.tar.gz", prefix, chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    fn manifest(format_version: u32, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let manifest = BackupManifest {
+            format_version,
+            created_at: "2024-01-31T12:00:00+00:00".to_string(),
+            tipi_version: "v3.0.0".to_string(),
+            cli_version: "v3.0.0".to_string(),
+            app_id: None,
+            includes_app_data: true,
+            apps: None,
+            database_dump: None,
+            checksums: files.iter().map(|(name, data)| (name.to_string(), checksum(data))).collect(),
+        };
+
+        serde_json::to_vec(&manifest).unwrap()
+    }
+
+    /**
+     * Write an archive entry by entry without the path checks of tar::Builder, like a crafted archive would
+     */
+    fn write_raw(path: &Path, entries: &[(&str, EntryType, &str, &[u8])]) {
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(path).unwrap(), Compression::default()));
+
+        for (name, entry_type, link, data) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn file<'a>(name: &'a str, data: &'a [u8]) -> (&'a str, EntryType, &'a str, &'a [u8]) {
+        (name, EntryType::Regular, "", data)
+    }
+
+    fn error_of(result: Result<ArchiveContent, Error>) -> String {
+        match result {
+            Ok(_) => panic!("the archive should have been refused"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        fs::create_dir_all(source.path().join("apps/nextcloud")).unwrap();
+        fs::create_dir_all(source.path().join("apps/skipped")).unwrap();
+        fs::write(source.path().join(".env"), "A=1\n").unwrap();
+        fs::write(source.path().join("apps/nextcloud/config.json"), "{}").unwrap();
+        fs::write(source.path().join("apps/nextcloud/run.sh"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(source.path().join("apps/nextcloud/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(source.path().join("apps/skipped/config.json"), "{}").unwrap();
+
+        let archive = source.path().join("backup.tar.gz");
+        let mut writer = ArchiveWriter::create(&archive).unwrap();
+        writer.add_file(&source.path().join(".env"), ".env").unwrap();
+        writer
+            .add_dir(&source.path().join("apps"), "apps", &|name| name == "apps/skipped")
+            .unwrap();
+        let written = writer.finish(BackupManifest {
+            format_version: MANIFEST_FORMAT_VERSION,
+            created_at: String::new(),
+            tipi_version: "v3.0.0".to_string(),
+            cli_version: "v3.0.0".to_string(),
+            app_id: None,
+            includes_app_data: false,
+            apps: None,
+            database_dump: None,
+            checksums: Default::default(),
+        });
+
+        assert_eq!(
+            written.unwrap().checksums.keys().collect::<Vec<_>>(),
+            [".env", "apps/nextcloud/config.json", "apps/nextcloud/run.sh"]
+        );
+        assert_eq!(fs::metadata(&archive).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let content = verify_archive(&archive).unwrap();
+        assert!(content.entries.contains(&"apps/nextcloud".to_string()));
+        assert!(!content.entries.iter().any(|entry| entry.starts_with("apps/skipped")));
+
+        let destination = tempfile::tempdir().unwrap();
+        extract_archive(&archive, destination.path()).unwrap();
+
+        assert_eq!(fs::read_to_string(destination.path().join(".env")).unwrap(), "A=1\n");
+        let run = fs::metadata(destination.path().join("apps/nextcloud/run.sh")).unwrap();
+        assert_eq!(run.permissions().mode() & 0o777, 0o755);
+        assert!(!destination.path().join("apps/skipped").exists());
+    }
+
+    #[test]
+    fn checks_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar.gz");
+
+        write_raw(&archive, &[file(".env", b"A=2"), file(MANIFEST_NAME, &manifest(1, &[(".env", b"A=1")]))]);
+        assert_eq!(error_of(verify_archive(&archive)), "Checksum mismatch for .env");
+
+        write_raw(
+            &archive,
+            &[
+                file(".env", b"A=1"),
+                file("state/extra", b""),
+                file(MANIFEST_NAME, &manifest(1, &[(".env", b"A=1")])),
+            ],
+        );
+        assert_eq!(error_of(verify_archive(&archive)), "state/extra is not listed in the manifest");
+
+        write_raw(&archive, &[file(MANIFEST_NAME, &manifest(1, &[(".env", b"A=1")]))]);
+        assert_eq!(
+            error_of(verify_archive(&archive)),
+            ".env is listed in the manifest but missing from the archive"
+        );
+
+        write_raw(&archive, &[file(".env", b"A=1")]);
+        assert_eq!(error_of(verify_archive(&archive)), "manifest.json is missing from the archive");
+
+        write_raw(&archive, &[file(".env", b"A=1"), file(MANIFEST_NAME, &manifest(2, &[(".env", b"A=1")]))]);
+        assert!(error_of(verify_archive(&archive)).contains("newer version of the CLI"));
+    }
+
+    #[test]
+    fn refuses_paths_outside_of_the_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar.gz");
+
+        for name in ["../evil", "state/../../evil", "/tmp/evil"] {
+            write_raw(&archive, &[file(name, b"evil"), file(MANIFEST_NAME, &manifest(1, &[(name, b"evil")]))]);
+            assert!(error_of(verify_archive(&archive)).starts_with("Refusing to extract"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn refuses_links_outside_of_the_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar.gz");
+        let destination = tempfile::tempdir().unwrap();
+
+        let links = [
+            (EntryType::Symlink, "/etc/cron.d/evil"),
+            (EntryType::Symlink, "../../evil"),
+            (EntryType::Symlink, "a/../../../evil"),
+            (EntryType::Link, "../evil"),
+        ];
+
+        for (entry_type, link) in links {
+            write_raw(&archive, &[("state/x", entry_type, link, b""), file(MANIFEST_NAME, &manifest(1, &[]))]);
+            let error = error_of(extract_archive(&archive, destination.path()));
+            assert!(error.starts_with("Refusing to extract state/x pointing to"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn refuses_to_write_through_an_extracted_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar.gz");
+        let destination = tempfile::tempdir().unwrap();
+
+        write_raw(
+            &archive,
+            &[
+                ("x", EntryType::Symlink, "state/target", b""),
+                file("x", b"evil"),
+                file(MANIFEST_NAME, &manifest(1, &[("x", b"evil")])),
+            ],
+        );
+
+        assert_eq!(
+            error_of(extract_archive(&archive, destination.path())),
+            "Refusing to extract x outside of the destination"
+        );
+        assert!(!destination.path().join("state/target").exists());
+    }
+
+    #[test]
+    fn keeps_links_inside_of_the_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar.gz");
+        let destination = tempfile::tempdir().unwrap();
+
+        write_raw(
+            &archive,
+            &[
+                file("state/settings.json", b"{}"),
+                ("state/link", EntryType::Symlink, "./settings.json", b""),
+                ("user-config/link", EntryType::Symlink, "../state/settings.json", b""),
+                file(MANIFEST_NAME, &manifest(1, &[("state/settings.json", b"{}")])),
+            ],
+        );
+
+        extract_archive(&archive, destination.path()).unwrap();
+
+        assert_eq!(fs::read_to_string(destination.path().join("user-config/link")).unwrap(), "{}");
+        assert_eq!(
+            fs::read_link(destination.path().join("state/link")).unwrap(),
+            Path::new("./settings.json")
+        );
+    }
+
+    #[test]
+    fn replaces_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let staging = tempfile::tempdir().unwrap();
+
+        fs::create_dir_all(root.path().join("apps/old/data")).unwrap();
+        fs::write(root.path().join("apps/old/data/file"), "old").unwrap();
+        fs::write(root.path().join("apps/old/stale"), "old").unwrap();
+        fs::write(root.path().join("removed"), "old").unwrap();
+        fs::create_dir_all(staging.path().join("apps/old/data")).unwrap();
+        fs::write(staging.path().join("apps/old/data/file"), "new").unwrap();
+        fs::create_dir_all(staging.path().join("apps/new")).unwrap();
+        fs::write(staging.path().join("apps/new/file"), "new").unwrap();
+
+        let paths = ["apps/old", "apps/new", "removed"].map(String::from);
+        replace_paths(root.path(), staging.path(), &paths).unwrap();
+
+        assert_eq!(fs::read_to_string(root.path().join("apps/old/data/file")).unwrap(), "new");
+        assert!(!root.path().join("apps/old/stale").exists());
+        assert_eq!(fs::read_to_string(root.path().join("apps/new/file")).unwrap(), "new");
+        assert!(!root.path().join("removed").exists());
+        assert_eq!(
+            fs::read_dir(root.path().join("apps")).unwrap().count(),
+            2,
+            "the previous version of apps/old should be removed"
+        );
+    }
+
+    #[test]
+    fn copies_paths() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        fs::create_dir_all(source.path().join("data/nested")).unwrap();
+        fs::write(source.path().join("data/nested/file"), "content").unwrap();
+        fs::set_permissions(source.path().join("data/nested/file"), fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink("nested/file", source.path().join("data/link")).unwrap();
+        fs::set_permissions(source.path().join("data"), fs::Permissions::from_mode(0o750)).unwrap();
+
+        copy_path(&source.path().join("data"), &destination.path().join("data")).unwrap();
+
+        let copy = destination.path().join("data");
+        assert_eq!(fs::read_to_string(copy.join("nested/file")).unwrap(), "content");
+        assert_eq!(fs::metadata(copy.join("nested/file")).unwrap().permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::metadata(&copy).unwrap().permissions().mode() & 0o777, 0o750);
+        assert_eq!(fs::read_link(copy.join("link")).unwrap(), Path::new("nested/file"));
+    }
+}
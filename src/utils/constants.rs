@@ -3,5 +3,15 @@ pub const DEFAULT_NGINX_PORT_SSL: &str = "443";
 pub const DEFAULT_POSTGRES_PORT: &str = "5432";
 pub const DEFAULT_DOMAIN: &str = "example.com";
 pub const DEFAULT_LOCAL_DOMAIN: &str = "tipi.local";
+pub const DEFAULT_APPS_REPO_URL: &str = "https://github.com/runtipi/runtipi-appstore";
+pub const DEFAULT_DNS_IP: &str = "9.9.9.9";
+pub const CORE_CONTAINERS: [&str; 6] = [
+    "tipi-reverse-proxy",
+    "tipi-docker-proxy",
+    "tipi-db",
+    "tipi-redis",
+    "tipi-worker",
+    "tipi-dashboard",
+];
 pub const DOCKER_COMPOSE_YML: &str = include_str!("../assets/docker-compose.yml");
 pub const VERSION: &str = include_str!("../assets/VERSION");
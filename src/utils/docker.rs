@@ -0,0 +1,313 @@
+use std::io::Error;
+use std::path::Path;
+
+use super::constants::DOCKER_COMPOSE_YML;
+
+#[derive(Debug, Clone)]
+pub struct Container {
+    pub name: String,
+    /// Docker compose project the container belongs to. Apps are started with their id as project name
+    pub project: Option<String>,
+    /// Raw state reported by docker eg: running, exited, paused, restarting, created
+    pub state: String,
+}
+
+impl Container {
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/**
+* List all containers known to docker, running or not
+*/
+pub fn list_containers() -> Result<Vec<Container>, Error> {
+    let output = std::process::Command::new("docker")
+        .arg("ps")
+        .arg("-a")
+        .arg("--format")
+        .arg("{{.Names}}\t{{.State}}\t{{.Label \"com.docker.compose.project\"}}")
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    let containers = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = line.split('\t');
+            let name = fields.next().unwrap_or_default().to_string();
+            let state = fields.next().unwrap_or_default().to_string();
+            let project = fields.next().filter(|p| !p.is_empty()).map(|p| p.to_string());
+
+            Container { name, project, state }
+        })
+        .collect();
+
+    Ok(containers)
+}
+
+#[derive(Debug, Clone)]
+pub struct ContainerHealth {
+    /// Raw state reported by docker eg: running, exited, restarting
+    pub state: String,
+    /// Result of the container healthcheck eg: healthy, unhealthy, starting. None if no healthcheck is defined
+    pub health: Option<String>,
+}
+
+impl ContainerHealth {
+    /**
+     * A container is considered healthy when it is running and its healthcheck, if any, passes
+     */
+    pub fn is_healthy(&self) -> bool {
+        self.state == "running" && self.health.as_deref().is_none_or(|h| h == "healthy")
+    }
+}
+
+/**
+* Inspect a single container. Returns None if the container does not exist
+*/
+pub fn inspect_container(name: &str) -> Result<Option<ContainerHealth>, Error> {
+    let output = std::process::Command::new("docker")
+        .arg("inspect")
+        .arg("--format")
+        .arg("{{.State.Status}}\t{{if .State.Health}}{{.State.Health.Status}}{{end}}")
+        .arg(name)
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No such") {
+            return Ok(None);
+        }
+        return Err(Error::other(stderr.trim().to_string()));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (state, health) = stdout.trim_end_matches('\n').split_once('\t').unwrap_or((stdout.trim(), ""));
+
+    Ok(Some(ContainerHealth {
+        state: state.trim().to_string(),
+        health: if health.trim().is_empty() {
+            None
+        } else {
+            Some(health.trim().to_string())
+        },
+    }))
+}
+
+/**
+* Names of the running containers belonging to the given compose projects (app ids)
+*/
+pub fn running_project_containers(projects: &[String]) -> Result<Vec<String>, Error> {
+    Ok(list_containers()?
+        .into_iter()
+        .filter(|c| c.is_running() && c.project.as_ref().is_some_and(|p| projects.contains(p)))
+        .map(|c| c.name)
+        .collect())
+}
+
+/**
+* Whether a core container is declared in the embedded docker-compose.yml. Some of them are legacy and only cleaned up
+*/
+pub fn is_compose_container(name: &str) -> bool {
+    DOCKER_COMPOSE_YML.contains(&format!("container_name: {}", name))
+}
+
+/**
+* Last lines of a container's logs (stdout and stderr)
+*/
+pub fn container_logs(name: &str, lines: usize) -> Result<String, Error> {
+    let output = std::process::Command::new("docker")
+        .arg("logs")
+        .arg("--tail")
+        .arg(lines.to_string())
+        .arg(name)
+        .output()?;
+
+    Ok(format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ))
+}
+
+/**
+* Compose arguments selecting the instance compose files (including user-config/tipi-compose.yml when present) and env file
+*/
+pub fn compose_file_args(root_folder: &Path) -> Vec<String> {
+    let user_compose_file = root_folder.join("user-config").join("tipi-compose.yml");
+
+    let mut args = vec!["-f".to_string(), root_folder.join("docker-compose.yml").display().to_string()];
+
+    if user_compose_file.exists() {
+        args.push("-f".to_string());
+        args.push(user_compose_file.display().to_string());
+    }
+
+    args.push("--env-file".to_string());
+    args.push(root_folder.join(".env").display().to_string());
+
+    args
+}
+
+/**
+* Load the images of an archive created by `docker save`
+*/
+pub fn load_images(archive: &Path) -> Result<(), Error> {
+    let output = std::process::Command::new("docker").arg("load").arg("--input").arg(archive).output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(())
+}
+
+/**
+* Image of every service declared in a compose file, in declaration order.
+* Only understands the block style used by the tipi compose files, not the whole YAML syntax
+*/
+pub fn compose_service_images(compose: &str) -> Vec<(String, String)> {
+    let mut images = vec![];
+    let mut in_services = false;
+    let mut service_indent: Option<usize> = None;
+    let mut service: Option<String> = None;
+
+    for line in compose.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+
+        if indent == 0 {
+            in_services = trimmed == "services:";
+            service = None;
+            continue;
+        }
+
+        if !in_services {
+            continue;
+        }
+
+        let service_indent = *service_indent.get_or_insert(indent);
+
+        if indent == service_indent {
+            service = trimmed.strip_suffix(':').map(|name| name.trim_matches(['"', '\'']).to_string());
+        } else if let (Some(service), Some(image)) = (&service, trimmed.strip_prefix("image:")) {
+            images.push((service.clone(), image.trim().trim_matches(['"', '\'']).to_string()));
+        }
+    }
+
+    images
+}
+
+/**
+* Id and repo digests of a local image, None if the image is not available locally
+*/
+pub fn inspect_image(image: &str) -> Result<Option<(String, Vec<String>)>, Error> {
+    let output = std::process::Command::new("docker")
+        .arg("image")
+        .arg("inspect")
+        .arg("--format")
+        .arg("{{.Id}}\t{{join .RepoDigests \",\"}}")
+        .arg(image)
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No such") {
+            return Ok(None);
+        }
+        return Err(Error::other(stderr.trim().to_string()));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (id, digests) = stdout.trim().split_once('\t').unwrap_or((stdout.trim(), ""));
+
+    Ok(Some((
+        id.to_string(),
+        digests.split(',').filter(|d| !d.is_empty()).map(String::from).collect(),
+    )))
+}
+
+pub fn pull_image(image: &str) -> Result<(), Error> {
+    let output = std::process::Command::new("docker").arg("pull").arg(image).output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(())
+}
+
+/**
+* Save images in a single archive that can be loaded with `docker load`
+*/
+pub fn save_images(images: &[String], destination: &Path) -> Result<(), Error> {
+    let output = std::process::Command::new("docker")
+        .arg("save")
+        .arg("--output")
+        .arg(destination)
+        .args(images)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_of_compose_services() {
+        let compose = r#"
+version: "3.9"
+
+services:
+  runtipi-reverse-proxy:
+    container_name: runtipi-reverse-proxy
+    # image: traefik:v2
+    image: traefik:v3.1
+    ports:
+      - ${NGINX_PORT:-80}:80
+
+  runtipi-db:
+    image: "postgres:14"
+    healthcheck:
+      test: ["CMD-SHELL", "pg_isready"]
+  'runtipi':
+    image: 'ghcr.io/runtipi/runtipi:${TIPI_VERSION}'
+  runtipi-build:
+    build: .
+
+networks:
+  tipi_main_network:
+    image: not-a-service
+"#;
+
+        assert_eq!(
+            compose_service_images(compose),
+            [
+                ("runtipi-reverse-proxy", "traefik:v3.1"),
+                ("runtipi-db", "postgres:14"),
+                ("runtipi", "ghcr.io/runtipi/runtipi:${TIPI_VERSION}"),
+            ]
+            .map(|(service, image)| (service.to_string(), image.to_string()))
+        );
+    }
+
+    #[test]
+    fn images_of_compose_file_without_services() {
+        assert!(compose_service_images("").is_empty());
+        assert!(compose_service_images("volumes:\n  data:\n    image: not-a-service\n").is_empty());
+    }
+}
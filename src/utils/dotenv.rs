@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+
+// In-memory model of a .env file that can be written back without losing anything the user added:
+//
+// # Custom apps repo             <- kept as is
+// export APPS_REPO_URL=https://  <- unmanaged key, kept as is
+// NGINX_PORT=80                  <- managed key, value updated in place
+// MOTD="Hello
+// world"                         <- quoted values can span several lines
+//
+// Keys set for the first time are appended at the end, in the order they are set.
+//
+// Values follow the syntax of the env files of docker compose:
+// - unquoted values are trimmed and end at an inline comment (` #`)
+// - single quoted values are taken literally
+// - double quoted values support the \n, \r, \t, \\, \" and \$ escapes
+// - unquoted and double quoted values are interpolated with the keys defined before them: ${VAR}, ${VAR:-default}, $VAR.
+//   $$ is a literal $
+
+#[derive(Debug, Clone)]
+enum Line {
+    Entry {
+        key: String,
+        value: String,
+        /// Original text of the entry, written back as long as the value is not changed
+        raw: Option<String>,
+    },
+    /// Comment, blank line or line that could not be parsed
+    Other(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EnvDocument {
+    lines: Vec<Line>,
+}
+
+/**
+* Replace `${VAR}`, `${VAR:-default}`, `${VAR-default}` and `$VAR` with their value, the way docker compose does
+*/
+pub fn interpolate(value: &str, env: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+
+        if let Some(stripped) = after.strip_prefix('$') {
+            result.push('$');
+            rest = stripped;
+        } else if let Some(braced) = after.strip_prefix('{') {
+            let end = braced.find('}').unwrap_or(braced.len());
+            let expression = &braced[..end];
+            // The operator, if any, follows the name eg: ${VAR:-default}
+            let name_end = expression.find(|c: char| !is_key_char(c)).unwrap_or(expression.len());
+            let (name, operator) = expression.split_at(name_end);
+
+            let replacement = if let Some(default) = operator.strip_prefix(":-") {
+                env.get(name).filter(|v| !v.is_empty()).cloned().unwrap_or(default.to_string())
+            } else if let Some(default) = operator.strip_prefix('-') {
+                env.get(name).cloned().unwrap_or(default.to_string())
+            } else {
+                env.get(name).cloned().unwrap_or_default()
+            };
+
+            result.push_str(&replacement);
+            rest = braced.get(end + 1..).unwrap_or_default();
+        } else {
+            let end = after.find(|c: char| !is_key_char(c)).unwrap_or(after.len());
+            if end == 0 {
+                result.push('$');
+            } else {
+                result.push_str(env.get(&after[..end]).map(String::as_str).unwrap_or_default());
+            }
+            rest = &after[end..];
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/**
+* Keys are made of letters, digits and _ and don't start with a digit, so that they can be interpolated
+*/
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(is_key_char)
+}
+
+/**
+* Split `KEY=value` into the key and the unparsed value. Accepts an `export ` prefix and spaces before the `=`
+*/
+fn split_entry(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('#') {
+        return None;
+    }
+
+    let trimmed = match trimmed.strip_prefix("export") {
+        Some(rest) if rest.starts_with([' ', '\t']) => rest.trim_start(),
+        _ => trimmed,
+    };
+
+    let (key, value) = trimmed.split_once('=')?;
+    let key = key.trim_end();
+
+    if !is_valid_key(key) {
+        return None;
+    }
+
+    Some((key, value))
+}
+
+/**
+* Content of a quoted value, without its quotes, or None if the closing quote is missing
+*/
+fn quoted_content(value: &str, quote: char) -> Option<&str> {
+    let mut escaped = false;
+
+    for (index, c) in value.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(&value[1..index]);
+        }
+    }
+
+    None
+}
+
+/**
+* Resolve the escapes of a double quoted value. Escaped `$` are doubled to survive the interpolation
+*/
+fn unescape(content: &str) -> String {
+    let mut result = String::new();
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('$') => result.push_str("$$"),
+            Some(other @ ('\\' | '"')) => result.push(other),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+/**
+* Value as it must be written in a .env file to be read back unchanged
+*/
+pub fn format_value(value: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c);
+
+    if value.chars().all(is_plain) {
+        value.to_string()
+    } else if !value.contains(['\'', '\n', '\r']) {
+        format!("'{}'", value)
+    } else {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "$$")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+            .replace('\t', "\\t");
+        format!("\"{}\"", escaped)
+    }
+}
+
+impl EnvDocument {
+    pub fn parse(content: &str) -> EnvDocument {
+        let source_lines = content.lines().collect::<Vec<&str>>();
+        let mut lines = vec![];
+        // Values defined so far, for the interpolation
+        let mut values: HashMap<String, String> = HashMap::new();
+        let mut index = 0;
+
+        while index < source_lines.len() {
+            let line = source_lines[index];
+            index += 1;
+
+            let (key, value) = match split_entry(line) {
+                Some(entry) => entry,
+                None => {
+                    lines.push(Line::Other(line.to_string()));
+                    continue;
+                }
+            };
+
+            let quote = value.trim_start().chars().next().filter(|c| *c == '"' || *c == '\'');
+
+            let (raw, value) = match quote {
+                Some(quote) => {
+                    // Quoted values continue on the next lines until the closing quote
+                    let mut raw = line.to_string();
+                    let mut value_text = value.trim_start().to_string();
+                    let mut end = index;
+
+                    while quoted_content(&value_text, quote).is_none() && end < source_lines.len() {
+                        raw.push('\n');
+                        raw.push_str(source_lines[end]);
+                        value_text.push('\n');
+                        value_text.push_str(source_lines[end]);
+                        end += 1;
+                    }
+
+                    match quoted_content(&value_text, quote) {
+                        Some(content) if quote == '"' => {
+                            index = end;
+                            (raw, interpolate(&unescape(content), &values))
+                        }
+                        Some(content) => {
+                            index = end;
+                            (raw, content.to_string())
+                        }
+                        // Unterminated quote, keep the line as is
+                        None => {
+                            lines.push(Line::Other(line.to_string()));
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    // A # starts a comment only when preceded by a space eg: KEY=a#b is a#b
+                    let comment = value
+                        .char_indices()
+                        .find(|(index, c)| *c == '#' && value[..*index].ends_with([' ', '\t']))
+                        .map(|(index, _)| index);
+                    let value = &value[..comment.unwrap_or(value.len())];
+
+                    (line.to_string(), interpolate(value.trim(), &values))
+                }
+            };
+
+            values.insert(key.to_string(), value.clone());
+            lines.push(Line::Entry {
+                key: key.to_string(),
+                value,
+                raw: Some(raw),
+            });
+        }
+
+        EnvDocument { lines }
+    }
+
+    /**
+     * Value of a key. When a key is defined more than once, the last definition wins
+     */
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Entry { key: k, value, .. } if k == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /**
+     * Update the value of a key where it is defined, or append it at the end of the document
+     */
+    pub fn set(&mut self, key: &str, value: &str) {
+        let mut found = false;
+
+        self.lines.retain_mut(|line| match line {
+            Line::Entry { key: k, value: v, raw } if k == key => {
+                // Only keep the first definition of a key
+                if found {
+                    return false;
+                }
+                found = true;
+
+                if v != value {
+                    *v = value.to_string();
+                    *raw = None;
+                }
+                true
+            }
+            _ => true,
+        });
+
+        if !found {
+            self.lines.push(Line::Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+                raw: None,
+            });
+        }
+    }
+
+    /**
+     * Keys in the order they appear in the document
+     */
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = vec![];
+        for line in &self.lines {
+            if let Line::Entry { key, .. } = line {
+                if !keys.contains(&key.as_str()) {
+                    keys.push(key);
+                }
+            }
+        }
+
+        keys
+    }
+
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.keys()
+            .into_iter()
+            .filter_map(|key| self.get(key).map(|value| (key.to_string(), value.to_string())))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for EnvDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Entry { raw: Some(raw), .. } => writeln!(f, "{}", raw)?,
+                Line::Entry { key, value, raw: None } => writeln!(f, "{}={}", key, format_value(value))?,
+                Line::Other(text) => writeln!(f, "{}", text)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_map(content: &str) -> HashMap<String, String> {
+        EnvDocument::parse(content).to_map()
+    }
+
+    fn value(content: &str, key: &str) -> Option<String> {
+        EnvDocument::parse(content).get(key).map(str::to_string)
+    }
+
+    #[test]
+    fn plain_values() {
+        let env = parse_map("A=1\nB = two  \n  C=\nD=with spaces inside\n");
+
+        assert_eq!(env["A"], "1");
+        assert_eq!(env["B"], "two");
+        assert_eq!(env["C"], "");
+        assert_eq!(env["D"], "with spaces inside");
+    }
+
+    #[test]
+    fn export_prefix() {
+        let env = parse_map("export A=1\nexport\tB=2\nexported=3\n");
+
+        assert_eq!(env["A"], "1");
+        assert_eq!(env["B"], "2");
+        assert_eq!(env["exported"], "3");
+    }
+
+    #[test]
+    fn comments_and_invalid_lines() {
+        let document = EnvDocument::parse("# A=1\n\nnot an entry\nMY-KEY=1\nmy.key=2\n1KEY=3\nB=2\n");
+
+        assert_eq!(document.keys(), vec!["B"]);
+        assert_eq!(document.to_string(), "# A=1\n\nnot an entry\nMY-KEY=1\nmy.key=2\n1KEY=3\nB=2\n");
+    }
+
+    #[test]
+    fn inline_comments() {
+        let env = parse_map("A=value # comment\nB=a#b\nC=a\t# comment\nD='quoted # kept' # comment\nE=\"x#y\"#no space\n");
+
+        assert_eq!(env["A"], "value");
+        assert_eq!(env["B"], "a#b");
+        assert_eq!(env["C"], "a");
+        assert_eq!(env["D"], "quoted # kept");
+        assert_eq!(env["E"], "x#y");
+    }
+
+    #[test]
+    fn single_quotes() {
+        let env = parse_map("A='  spaced  '\nB='$HOME ${X} \\n \"x\"'\n");
+
+        assert_eq!(env["A"], "  spaced  ");
+        assert_eq!(env["B"], "$HOME ${X} \\n \"x\"");
+    }
+
+    #[test]
+    fn double_quotes_and_escapes() {
+        let env = parse_map(
+            r#"A="a\nb\tc\\d\"e\$f\r"
+B="\q"
+"#,
+        );
+
+        assert_eq!(env["A"], "a\nb\tc\\d\"e$f\r");
+        assert_eq!(env["B"], "\\q");
+    }
+
+    #[test]
+    fn multiline_values() {
+        let content = "A=\"first\nsecond\"\nB='one\ntwo\n'\nC=3\n";
+        let document = EnvDocument::parse(content);
+
+        assert_eq!(document.get("A"), Some("first\nsecond"));
+        assert_eq!(document.get("B"), Some("one\ntwo\n"));
+        assert_eq!(document.get("C"), Some("3"));
+        assert_eq!(document.to_string(), content);
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        let document = EnvDocument::parse("A=\"never closed\nB=2\nC='also open\n");
+
+        assert_eq!(document.get("A"), None);
+        assert_eq!(document.get("B"), Some("2"));
+        assert_eq!(document.get("C"), None);
+        assert_eq!(document.to_string(), "A=\"never closed\nB=2\nC='also open\n");
+    }
+
+    #[test]
+    fn interpolation() {
+        let env = parse_map(
+            "HOST=example.com\nEMPTY=\nA=${HOST}\nB=$HOST/path\nC=${MISSING}\nD=${EMPTY:-default}\nE=${EMPTY-default}\nF=${MISSING-default}\nG=${HOST:-default}\nH=\"${HOST}\"\nI='${HOST}'\n",
+        );
+
+        assert_eq!(env["A"], "example.com");
+        assert_eq!(env["B"], "example.com/path");
+        assert_eq!(env["C"], "");
+        assert_eq!(env["D"], "default");
+        assert_eq!(env["E"], "");
+        assert_eq!(env["F"], "default");
+        assert_eq!(env["G"], "example.com");
+        assert_eq!(env["H"], "example.com");
+        assert_eq!(env["I"], "${HOST}");
+    }
+
+    #[test]
+    fn interpolation_uses_previous_keys_only() {
+        let env = parse_map("A=${B:-unset}\nB=1\nC=${B}\n");
+
+        assert_eq!(env["A"], "unset");
+        assert_eq!(env["C"], "1");
+    }
+
+    #[test]
+    fn interpolation_name_before_operator() {
+        let env = HashMap::from([("MY".to_string(), "a".to_string()), ("MY_KEY".to_string(), "1".to_string())]);
+
+        assert_eq!(interpolate("${MY_KEY}", &env), "1");
+        assert_eq!(interpolate("${MY_KEY-d}", &env), "1");
+        assert_eq!(interpolate("${MY-KEY}", &env), "a");
+        assert_eq!(interpolate("${NOPE-KEY}", &env), "KEY");
+
+        // - is not allowed in keys, so ${MY-KEY} can only be read as MY with a default
+        let document = EnvDocument::parse("MY-KEY=1\nX=${MY-KEY}\n");
+        assert_eq!(document.get("MY-KEY"), None);
+        assert_eq!(document.get("X"), Some("KEY"));
+    }
+
+    #[test]
+    fn dollar_escapes() {
+        let env = parse_map("A=$$HOME\nB=\"\\$HOME\"\nC=cost: 5$\nD=$ alone\nE=${UNCLOSED\n");
+
+        assert_eq!(env["A"], "$HOME");
+        assert_eq!(env["B"], "$HOME");
+        assert_eq!(env["C"], "cost: 5$");
+        assert_eq!(env["D"], "$ alone");
+        assert_eq!(env["E"], "");
+    }
+
+    #[test]
+    fn last_definition_wins() {
+        let mut document = EnvDocument::parse("A=1\nB=2\nA=3\n");
+        assert_eq!(document.get("A"), Some("3"));
+
+        document.set("A", "4");
+        assert_eq!(document.to_string(), "A=4\nB=2\n");
+    }
+
+    #[test]
+    fn set_keeps_untouched_lines() {
+        let mut document = EnvDocument::parse("# comment\nexport CUSTOM='kept'   # as is\nA=1\n");
+        document.set("A", "2");
+        document.set("NEW", "value");
+        document.set("CUSTOM", "kept");
+
+        assert_eq!(document.to_string(), "# comment\nexport CUSTOM='kept'   # as is\nA=2\nNEW=value\n");
+    }
+
+    #[test]
+    fn format_value_round_trips() {
+        let values = [
+            "",
+            "plain",
+            "https://example.com:8080/path?x=1",
+            "with spaces",
+            " leading and trailing ",
+            "a#b",
+            "a #b",
+            "$HOME",
+            "${VAR:-d}",
+            "$$",
+            "single'quote",
+            "double\"quote",
+            "both ' and \"",
+            "back\\slash",
+            "multi\nline",
+            "line with ' and $VAR\nnext",
+            "tab\there",
+            "carriage\r\nreturn",
+        ];
+
+        for original in values {
+            let content = format!("KEY={}\n", format_value(original));
+            assert_eq!(
+                value(&content, "KEY").as_deref(),
+                Some(original),
+                "{:?} was written as {:?}",
+                original,
+                content
+            );
+        }
+    }
+}
@@ -1,65 +1,58 @@
 use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use std::io::Error;
 
-use crate::utils::constants::{DEFAULT_NGINX_PORT, DEFAULT_NGINX_PORT_SSL};
+use sha2::{Digest, Sha256};
+
+use crate::utils::constants::{DEFAULT_APPS_REPO_URL, DEFAULT_DNS_IP, DEFAULT_NGINX_PORT, DEFAULT_NGINX_PORT_SSL};
+use crate::utils::dotenv::EnvDocument;
+use crate::utils::release;
 use crate::utils::schemas;
 use crate::utils::seed::generate_seed;
+use crate::utils::settings;
 use crate::utils::system::{derive_entropy, get_architecture, get_internal_ip, get_seed};
 
 use super::constants::{DEFAULT_DOMAIN, DEFAULT_LOCAL_DOMAIN, DEFAULT_POSTGRES_PORT};
 use super::schemas::StringOrInt;
 
-pub fn get_env_map() -> HashMap<String, String> {
-    let root_folder: PathBuf = env::current_dir().expect("Unable to get current directory");
+pub fn get_env_map() -> Result<HashMap<String, String>, Error> {
+    let root_folder: PathBuf = env::current_dir()?;
     let env_file_path = root_folder.join(".env");
 
-    let env_file = std::fs::read_to_string(&env_file_path).expect("Unable to read .env file");
-    env_string_to_map(&env_file)
+    let env_file = std::fs::read_to_string(&env_file_path)?;
+    Ok(env_string_to_map(&env_file))
 }
 
-pub fn get_env_value(key: &str) -> Option<String> {
-    let env_map = get_env_map();
-    env_map.get(key).map(|value| value.to_string())
+pub fn env_string_to_map(env_string: &str) -> HashMap<String, String> {
+    EnvDocument::parse(env_string).to_map()
 }
 
-pub fn env_string_to_map(env_string: &str) -> std::collections::HashMap<String, String> {
-    let mut env_map = std::collections::HashMap::new();
+/**
+* Folder containing app-data, mounted as /storage by compose: STORAGE_PATH of the .env file or the root folder when not set
+*/
+pub fn storage_folder(root_folder: &Path) -> PathBuf {
+    let env_file = std::fs::read_to_string(root_folder.join(".env")).unwrap_or_default();
 
-    for line in env_string.lines() {
-        // If line is empty or starts with #, skip it
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-
-        let mut split = line.splitn(2, '=');
-        match (split.next(), split.next()) {
-            (Some(key), Some(value)) => {
-                env_map.insert(key.to_string(), value.to_string());
-            }
-            _ => {
-                eprintln!("Warning: Line '{}' is not in the correct format", line);
-            }
-        }
+    match env_string_to_map(&env_file).get("STORAGE_PATH") {
+        Some(path) if !path.trim().is_empty() => root_folder.join(path),
+        _ => root_folder.to_path_buf(),
     }
-
-    env_map
 }
 
-pub fn env_map_to_string(env_map: &HashMap<String, String>) -> String {
-    let mut env_string = String::new();
-
-    for (key, value) in env_map {
-        env_string.push_str(&format!("{}={}\n", key, value));
-    }
-
-    env_string
+/**
+* Id of an apps repo, the name of its folder in repos/. Derived from its url so that each repo gets its own folder
+*/
+pub fn apps_repo_id(apps_repo_url: &str) -> String {
+    hex::encode(Sha256::digest(apps_repo_url.as_bytes()))
 }
 
 pub fn generate_env_file(custom_env_file_path: Option<PathBuf>) -> Result<(), Error> {
-    let root_folder: PathBuf = env::current_dir().expect("Unable to get current directory");
+    write_env_file(&env::current_dir()?, custom_env_file_path)
+}
+
+fn write_env_file(root_folder: &Path, custom_env_file_path: Option<PathBuf>) -> Result<(), Error> {
     let env_file_path = root_folder.join(".env");
     let state_path = root_folder.join("state");
     let settings_file_path = state_path.join("settings.json");
@@ -77,97 +70,220 @@ pub fn generate_env_file(custom_env_file_path: Option<PathBuf>) -> Result<(), Er
         std::fs::write(&settings_file_path, "{}")?;
     }
 
-    generate_seed(&root_folder)?;
+    generate_seed(root_folder)?;
 
     let env_file = std::fs::read_to_string(&env_file_path)?;
-    let env_map = env_string_to_map(&env_file);
+    let mut env_document = EnvDocument::parse(&env_file);
 
     let json_string = std::fs::read_to_string(&settings_file_path)?;
+    if let Some(e) = settings::issues_error(&settings::check(&json_string)) {
+        return Err(e);
+    }
     let parsed_json: schemas::SettingsSchema = serde_json::from_str(&json_string)?;
 
     let version = std::fs::read_to_string(root_folder.join("VERSION"))?;
 
-    // Create a new env map with the default values
-    let mut new_env_map: HashMap<String, String> = HashMap::new();
-
-    let seed = get_seed(&root_folder);
-    let postgres_password: String = env_map
+    let seed = get_seed(root_folder);
+    let postgres_password: String = env_document
         .get("POSTGRES_PASSWORD")
-        .unwrap_or(&derive_entropy("postgres_password", &seed))
-        .to_string();
-    let redis_password: String = env_map
+        .map(String::from)
+        .unwrap_or_else(|| derive_entropy("postgres_password", &seed));
+    let redis_password: String = env_document
         .get("REDIS_PASSWORD")
-        .unwrap_or(&derive_entropy("redis_password", &seed))
-        .to_string();
-
-    if parsed_json.storage_path.is_some() {
-        // Test if the storage path is valid
-        let storage_path = PathBuf::from(&parsed_json.storage_path.as_ref().unwrap());
-
-        if !storage_path.exists() {
-            return Err(Error::new(
-                std::io::ErrorKind::NotFound,
-                format!(
-                    "Storage path '{}' does not exist on your system. Make sure it is an absolute path or remove it from settings.json.",
-                    storage_path.display()
-                ),
-            ));
-        }
+        .map(String::from)
+        .unwrap_or_else(|| derive_entropy("redis_password", &seed));
+    let jwt_secret: String = env_document
+        .get("JWT_SECRET")
+        .map(String::from)
+        .unwrap_or_else(|| derive_entropy("jwt_secret", &seed));
+
+    let bool_setting = |setting: Option<bool>, default: bool| setting.unwrap_or(default).to_string();
+
+    let apps_repo_url = parsed_json.apps_repo_url.unwrap_or(DEFAULT_APPS_REPO_URL.to_string());
+
+    // Keys managed by the CLI, in the order they are written to a new .env file
+    let managed_values: Vec<(&str, String)> = vec![
+        ("INTERNAL_IP", parsed_json.internal_ip.unwrap_or(get_internal_ip())),
+        ("ARCHITECTURE", get_architecture().unwrap().to_string()),
+        ("TIPI_VERSION", release::image_tag(&version)),
+        ("ROOT_FOLDER_HOST", root_folder.display().to_string()),
+        (
+            "NGINX_PORT",
+            parsed_json.nginx_port.unwrap_or(StringOrInt::from(DEFAULT_NGINX_PORT)).as_string(),
+        ),
+        (
+            "NGINX_PORT_SSL",
+            parsed_json
+                .nginx_ssl_port
+                .unwrap_or(StringOrInt::from(DEFAULT_NGINX_PORT_SSL))
+                .as_string(),
+        ),
+        ("STORAGE_PATH", parsed_json.storage_path.unwrap_or(root_folder.display().to_string())),
+        ("POSTGRES_PASSWORD", postgres_password),
+        (
+            "POSTGRES_PORT",
+            parsed_json.postgres_port.unwrap_or(StringOrInt::from(DEFAULT_POSTGRES_PORT)).as_string(),
+        ),
+        ("POSTGRES_HOST", "tipi-db".to_string()),
+        ("REDIS_HOST", "tipi-redis".to_string()),
+        ("REDIS_PASSWORD", redis_password),
+        ("DOMAIN", parsed_json.domain.unwrap_or(DEFAULT_DOMAIN.to_string())),
+        ("LOCAL_DOMAIN", parsed_json.local_domain.unwrap_or(DEFAULT_LOCAL_DOMAIN.to_string())),
+        ("POSTGRES_DBNAME", "tipi".to_string()),
+        ("POSTGRES_USERNAME", "tipi".to_string()),
+        ("JWT_SECRET", jwt_secret),
+        ("APPS_REPO_ID", apps_repo_id(&apps_repo_url)),
+        ("APPS_REPO_URL", apps_repo_url),
+        ("DNS_IP", parsed_json.dns_ip.unwrap_or(DEFAULT_DNS_IP.to_string())),
+        ("DEMO_MODE", bool_setting(parsed_json.demo_mode, false)),
+        ("GUEST_DASHBOARD", bool_setting(parsed_json.guest_dashboard, false)),
+        ("ALLOW_ERROR_MONITORING", bool_setting(parsed_json.allow_error_monitoring, false)),
+        ("ALLOW_AUTO_THEMES", bool_setting(parsed_json.allow_auto_themes, true)),
+        ("PERSIST_TRAEFIK_CONFIG", bool_setting(parsed_json.persist_traefik_config, false)),
+    ];
+
+    // Only managed keys are updated, comments and keys added by the user are kept where they are
+    for (key, value) in &managed_values {
+        env_document.set(key, value);
     }
 
-    // Insert the default values into the new env map
-    new_env_map.insert("INTERNAL_IP".to_string(), parsed_json.internal_ip.unwrap_or(get_internal_ip()));
-    new_env_map.insert("ARCHITECTURE".to_string(), get_architecture().unwrap().to_string());
-    new_env_map.insert("TIPI_VERSION".to_string(), version);
-    new_env_map.insert("ROOT_FOLDER_HOST".to_string(), root_folder.display().to_string());
-    new_env_map.insert(
-        "NGINX_PORT".to_string(),
-        parsed_json.nginx_port.unwrap_or(StringOrInt::from(DEFAULT_NGINX_PORT)).as_string(),
-    );
-    new_env_map.insert(
-        "NGINX_PORT_SSL".to_string(),
-        parsed_json
-            .nginx_ssl_port
-            .unwrap_or(StringOrInt::from(DEFAULT_NGINX_PORT_SSL))
-            .as_string(),
-    );
-    new_env_map.insert(
-        "STORAGE_PATH".to_string(),
-        parsed_json.storage_path.unwrap_or(root_folder.display().to_string()),
-    );
-    new_env_map.insert("POSTGRES_PASSWORD".to_string(), postgres_password);
-    new_env_map.insert(
-        "POSTGRES_PORT".to_string(),
-        parsed_json.postgres_port.unwrap_or(StringOrInt::from(DEFAULT_POSTGRES_PORT)).as_string(),
-    );
-    new_env_map.insert("POSTGRES_HOST".to_string(), "tipi-db".to_string());
-    new_env_map.insert("REDIS_HOST".to_string(), "tipi-redis".to_string());
-    new_env_map.insert("REDIS_PASSWORD".to_string(), redis_password);
-    new_env_map.insert("DOMAIN".to_string(), parsed_json.domain.unwrap_or(DEFAULT_DOMAIN.to_string()));
-    new_env_map.insert(
-        "LOCAL_DOMAIN".to_string(),
-        parsed_json.local_domain.unwrap_or(DEFAULT_LOCAL_DOMAIN.to_string()),
-    );
-
     if let Some(custom_env_file_path) = custom_env_file_path {
         let custom_env_file = std::fs::read_to_string(&custom_env_file_path)?;
+        let custom_env_document = EnvDocument::parse(&custom_env_file);
+
+        for key in custom_env_document.keys() {
+            if let Some(value) = custom_env_document.get(key) {
+                env_document.set(key, value);
+            }
+        }
+    }
 
-        let custom_env_map = env_string_to_map(&custom_env_file);
+    std::fs::write(&env_file_path, env_document.to_string())?;
 
-        let mut merged_env_map = new_env_map.clone();
+    Ok(())
+}
 
-        for (key, value) in custom_env_map {
-            merged_env_map.insert(key.clone(), value);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root_folder(version: &str, settings: &str, env_file: Option<&str>) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("state")).unwrap();
+        std::fs::write(root.path().join("VERSION"), version).unwrap();
+        std::fs::write(root.path().join("state/settings.json"), settings).unwrap();
+        if let Some(env_file) = env_file {
+            std::fs::write(root.path().join(".env"), env_file).unwrap();
         }
 
-        let merged_env_string = env_map_to_string(&merged_env_map);
+        root
+    }
+
+    fn generate(root: &Path, custom_env_file: Option<PathBuf>) -> String {
+        write_env_file(root, custom_env_file).unwrap();
+        std::fs::read_to_string(root.join(".env")).unwrap()
+    }
 
-        std::fs::write(&env_file_path, merged_env_string)?;
-    } else {
-        let new_env_string = env_map_to_string(&new_env_map);
+    #[test]
+    fn new_env_file() {
+        let root = root_folder("v3.0.0\n", "{}", None);
+        let env = env_string_to_map(&generate(root.path(), None));
+
+        assert_eq!(env["TIPI_VERSION"], "v3.0.0");
+        assert_eq!(env["ROOT_FOLDER_HOST"], root.path().display().to_string());
+        assert_eq!(env["STORAGE_PATH"], root.path().display().to_string());
+        assert_eq!(env["NGINX_PORT"], DEFAULT_NGINX_PORT.to_string());
+        assert_eq!(env["POSTGRES_PASSWORD"].len(), 64);
+        assert!(root.path().join("state/seed").exists());
+    }
 
-        std::fs::write(&env_file_path, new_env_string)?;
+    #[test]
+    fn keeps_comments_unknown_keys_and_order() {
+        let root = root_folder(
+            "nightly",
+            "{}",
+            Some("# Added by hand\nMY_KEY=1\nNGINX_PORT=1234\nJWT_SECRET=secret\n\nINTERNAL_IP=10.0.0.1 # lan\n"),
+        );
+
+        let first = generate(root.path(), None);
+        assert!(
+            first.starts_with(&format!(
+                "# Added by hand\nMY_KEY=1\nNGINX_PORT={}\nJWT_SECRET=secret\n\nINTERNAL_IP=",
+                DEFAULT_NGINX_PORT
+            )),
+            "{}",
+            first
+        );
+        assert_eq!(env_string_to_map(&first)["TIPI_VERSION"], "nightly");
+
+        // Regenerating with the same settings doesn't change anything
+        assert_eq!(generate(root.path(), None), first);
     }
 
-    Ok(())
+    #[test]
+    fn custom_env_file_overrides_values() {
+        let root = root_folder("v3.0.0", "{}", None);
+        let custom = root.path().join("custom.env");
+        std::fs::write(&custom, "NGINX_PORT=8080\nEXTRA=value\n").unwrap();
+
+        let env = env_string_to_map(&generate(root.path(), Some(custom)));
+
+        assert_eq!(env["NGINX_PORT"], "8080");
+        assert_eq!(env["EXTRA"], "value");
+    }
+
+    #[test]
+    fn refuses_invalid_settings() {
+        let root = root_folder("v3.0.0", r#"{ "port": "abc" }"#, Some("MY_KEY=1\n"));
+
+        assert!(write_env_file(root.path(), None).is_err());
+        assert_eq!(std::fs::read_to_string(root.path().join(".env")).unwrap(), "MY_KEY=1\n");
+    }
+
+    #[test]
+    fn dashboard_settings() {
+        let settings = r#"{
+            "appsRepoUrl": "https://github.com/me/apps",
+            "dnsIp": "1.1.1.1",
+            "demoMode": true,
+            "guestDashboard": true,
+            "allowAutoThemes": false
+        }"#;
+        let root = root_folder("v3.0.0", settings, None);
+        let env = env_string_to_map(&generate(root.path(), None));
+
+        assert_eq!(env["APPS_REPO_URL"], "https://github.com/me/apps");
+        assert_eq!(env["APPS_REPO_ID"], hex::encode(Sha256::digest(b"https://github.com/me/apps")));
+        assert_eq!(env["DNS_IP"], "1.1.1.1");
+        assert_eq!(env["DEMO_MODE"], "true");
+        assert_eq!(env["GUEST_DASHBOARD"], "true");
+        assert_eq!(env["ALLOW_AUTO_THEMES"], "false");
+        assert_eq!(env["ALLOW_ERROR_MONITORING"], "false");
+    }
+
+    #[test]
+    fn removed_dashboard_settings_fall_back_to_their_default() {
+        let env_file = "DEMO_MODE=true\nALLOW_AUTO_THEMES=false\nAPPS_REPO_URL=https://github.com/me/apps\nDNS_IP=1.1.1.1\n";
+        let root = root_folder("v3.0.0", "{}", Some(env_file));
+        let env = env_string_to_map(&generate(root.path(), None));
+
+        assert_eq!(env["DEMO_MODE"], "false");
+        assert_eq!(env["ALLOW_AUTO_THEMES"], "true");
+        assert_eq!(env["APPS_REPO_URL"], DEFAULT_APPS_REPO_URL);
+        assert_eq!(env["APPS_REPO_ID"], apps_repo_id(DEFAULT_APPS_REPO_URL));
+        assert_eq!(env["DNS_IP"], DEFAULT_DNS_IP);
+    }
+
+    #[test]
+    fn apps_repo_ids() {
+        assert_eq!(
+            apps_repo_id("https://github.com/runtipi/runtipi-appstore"),
+            apps_repo_id("https://github.com/runtipi/runtipi-appstore")
+        );
+        assert_ne!(
+            apps_repo_id("https://github.com/runtipi/runtipi-appstore"),
+            apps_repo_id("https://github.com/me/apps")
+        );
+        assert_eq!(apps_repo_id("").len(), 64);
+    }
 }
@@ -1,5 +1,16 @@
+pub mod app_form;
+pub mod backup;
 pub mod constants;
+pub mod docker;
+pub mod dotenv;
 pub mod env;
+pub mod release;
+pub mod release_source;
+pub mod rollback;
 pub mod schemas;
 pub mod seed;
+pub mod settings;
 pub mod system;
+#[cfg(test)]
+pub mod test_server;
+pub mod worker_api;
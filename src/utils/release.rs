@@ -0,0 +1,550 @@
+use std::fs::{self, File};
+use std::io::{Error, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use self_update::update::Release;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Public key used to verify the signature of release checksum files, as 64 hex characters.
+// Embedded at build time with RUNTIPI_RELEASE_PUBLIC_KEY. When set, unsigned releases are refused
+pub const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("RUNTIPI_RELEASE_PUBLIC_KEY");
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateKind {
+    Major,
+    Minor,
+    Patch,
+}
+
+impl std::fmt::Display for UpdateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateKind::Major => write!(f, "major"),
+            UpdateKind::Minor => write!(f, "minor"),
+            UpdateKind::Patch => write!(f, "patch"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseChannel {
+    /// Stable releases only
+    Stable,
+    /// Stable releases and pre-releases eg: v3.1.0-beta.1
+    Beta,
+    /// The nightly build, published under the nightly tag
+    Nightly,
+}
+
+impl std::fmt::Display for ReleaseChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReleaseChannel::Stable => write!(f, "stable"),
+            ReleaseChannel::Beta => write!(f, "beta"),
+            ReleaseChannel::Nightly => write!(f, "nightly"),
+        }
+    }
+}
+
+impl ReleaseChannel {
+    /**
+     * Most recent release of the channel, by semver order
+     */
+    pub fn latest<'a>(&self, releases: &'a [Release]) -> Option<&'a Release> {
+        if *self == ReleaseChannel::Nightly {
+            return releases.iter().find(|r| r.version == "nightly");
+        }
+
+        releases
+            .iter()
+            .filter_map(|r| parse_version(&r.version).map(|version| (r, version)))
+            .filter(|(_, version)| *self == ReleaseChannel::Beta || version.pre.is_empty())
+            .max_by(|a, b| a.1.cmp(&b.1))
+            .map(|(r, _)| r)
+    }
+}
+
+/**
+* Docker image tag of the runtipi images for a version eg: 3.0.0 -> v3.0.0, nightly -> nightly
+*/
+pub fn image_tag(version: &str) -> String {
+    match parse_version(version) {
+        Some(version) => format!("v{}", version),
+        None => version.trim().to_string(),
+    }
+}
+
+/**
+* Parse a version as found in the VERSION file or in a release tag eg: v3.0.0
+*/
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim();
+    Version::parse(version.strip_prefix(['v', 'V']).unwrap_or(version)).ok()
+}
+
+/**
+* Kind of update going from `current` to `new`, None if `new` is not more recent
+*/
+pub fn update_kind(current: &Version, new: &Version) -> Option<UpdateKind> {
+    if new <= current {
+        None
+    } else if new.major != current.major {
+        Some(UpdateKind::Major)
+    } else if new.minor != current.minor {
+        Some(UpdateKind::Minor)
+    } else {
+        Some(UpdateKind::Patch)
+    }
+}
+
+/**
+* Names of the files a release may publish the checksum of an asset in, by order of preference
+*/
+pub fn checksum_file_names(asset_name: &str) -> Vec<String> {
+    vec![format!("{}.sha256", asset_name), "checksums.txt".to_string(), "SHA256SUMS".to_string()]
+}
+
+/**
+* Detached signature of a checksum file eg: checksums.txt.sig
+*/
+pub fn signature_file_name(checksum_file_name: &str) -> String {
+    format!("{}.sig", checksum_file_name)
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/**
+* Find the checksum of a file in a checksum file. Supports the sha256sum format (`<hash>  <name>`) and files containing a single hash
+*/
+pub fn find_checksum(checksums: &str, file_name: &str) -> Option<String> {
+    let lines = checksums.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<&str>>();
+
+    if let [line] = lines.as_slice() {
+        if !line.contains(char::is_whitespace) {
+            return Some(line.to_lowercase());
+        }
+    }
+
+    lines.iter().find_map(|line| {
+        let (hash, name) = line.split_once(char::is_whitespace)?;
+        // sha256sum prefixes the name with * in binary mode
+        let name = name.trim().trim_start_matches('*');
+        let name = name.rsplit('/').next().unwrap_or(name);
+
+        if name == file_name {
+            Some(hash.to_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+/**
+* Check an ed25519 signature. The signature and public key are hex encoded
+*/
+pub fn verify_signature(message: &[u8], signature: &str, public_key: &str) -> Result<(), String> {
+    let signature = hex::decode(signature.trim()).map_err(|e| format!("Invalid signature: {}", e))?;
+    let public_key = hex::decode(public_key.trim()).map_err(|e| format!("Invalid public key: {}", e))?;
+
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(message, &signature)
+        .map_err(|_| "The signature does not match the release public key".to_string())
+}
+
+/**
+* Verify an asset against the checksum file found next to it, and the signature of that checksum file
+* when a release public key is embedded. Works on any folder laid out like a release eg:
+*
+* release/
+* ├── runtipi-cli-linux-x86_64.tar.gz
+* ├── runtipi-cli-linux-x86_64.tar.gz.sha256
+* └── runtipi-cli-linux-x86_64.tar.gz.sha256.sig
+*/
+pub fn verify_release_dir(release_dir: &Path, asset_name: &str) -> Result<(), String> {
+    verify_release_dir_with_key(release_dir, asset_name, RELEASE_PUBLIC_KEY)
+}
+
+fn verify_release_dir_with_key(release_dir: &Path, asset_name: &str, public_key: Option<&str>) -> Result<(), String> {
+    let checksum_file_name = checksum_file_names(asset_name)
+        .into_iter()
+        .find(|name| release_dir.join(name).is_file())
+        .ok_or_else(|| format!("No checksum file found for {}", asset_name))?;
+
+    let checksums = fs::read_to_string(release_dir.join(&checksum_file_name)).map_err(|e| format!("Unable to read {}: {}", checksum_file_name, e))?;
+
+    if let Some(public_key) = public_key {
+        let signature_file_name = signature_file_name(&checksum_file_name);
+        let signature = fs::read_to_string(release_dir.join(&signature_file_name))
+            .map_err(|_| format!("{} is not signed ({} is missing)", checksum_file_name, signature_file_name))?;
+
+        verify_signature(checksums.as_bytes(), &signature, public_key).map_err(|e| format!("Invalid signature for {}: {}", checksum_file_name, e))?;
+    }
+
+    let expected =
+        find_checksum(&checksums, asset_name).ok_or_else(|| format!("{} does not contain a checksum for {}", checksum_file_name, asset_name))?;
+    let actual = sha256_file(&release_dir.join(asset_name)).map_err(|e| format!("Unable to read {}: {}", asset_name, e))?;
+
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for {}: expected {} but got {}. The download may be corrupted or tampered with",
+            asset_name, expected, actual
+        ));
+    }
+
+    Ok(())
+}
+
+/**
+* Name of the binary shipped in a release tarball eg: runtipi-cli-linux-x86_64.tar.gz contains runtipi-cli-linux-x86_64
+*/
+pub fn binary_name(asset_name: &str) -> String {
+    let name = asset_name.strip_suffix(".tar.gz").or_else(|| asset_name.strip_suffix(".tgz"));
+    name.unwrap_or(asset_name).to_string()
+}
+
+/**
+* Extract the CLI binary of a release tarball into `destination`, ignoring every other entry.
+* The binary is checked to be an ELF executable for `arch` (x86_64 or aarch64) and made executable
+*/
+pub fn extract_binary(tarball: &Path, asset_name: &str, arch: &str, destination: &Path) -> Result<PathBuf, String> {
+    let expected_names = [binary_name(asset_name), "runtipi-cli".to_string()];
+
+    let file = File::open(tarball).map_err(|e| format!("Unable to open {}: {}", asset_name, e))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let entries = archive.entries().map_err(|e| format!("Unable to read {}: {}", asset_name, e))?;
+
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("Unable to read {}: {}", asset_name, e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let entry_path = entry.path().map_err(|e| e.to_string())?.to_path_buf();
+        let file_name = entry_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if !expected_names.contains(&file_name) {
+            continue;
+        }
+
+        let binary_path = destination.join(&file_name);
+        let mut binary = File::create(&binary_path).map_err(|e| format!("Unable to create {}: {}", binary_path.display(), e))?;
+        std::io::copy(&mut entry, &mut binary).map_err(|e| format!("Unable to extract {}: {}", file_name, e))?;
+
+        check_elf(&binary_path, arch)?;
+
+        fs::set_permissions(&binary_path, fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Unable to make {} executable: {}", file_name, e))?;
+
+        return Ok(binary_path);
+    }
+
+    Err(format!("{} does not contain a {} binary", asset_name, expected_names[0]))
+}
+
+/**
+* Make sure a file is a 64 bits little endian ELF executable for the given architecture
+*/
+fn check_elf(path: &Path, arch: &str) -> Result<(), String> {
+    // e_machine values from the ELF specification
+    let expected_machine: u16 = match arch {
+        "x86_64" => 0x3e,
+        "aarch64" => 0xb7,
+        _ => return Err(format!("Unsupported architecture {}", arch)),
+    };
+
+    let mut header = [0u8; 20];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|_| format!("{} is not an executable", path.display()))?;
+
+    if &header[..4] != b"\x7fELF" || header[4] != 2 || header[5] != 1 {
+        return Err(format!("{} is not a 64 bits linux executable", path.display()));
+    }
+
+    let machine = u16::from_le_bytes([header[18], header[19]]);
+    if machine != expected_machine {
+        return Err(format!("{} is not built for {}", path.display(), arch));
+    }
+
+    Ok(())
+}
+
+/**
+* Extract an offline update bundle into `destination`
+*/
+pub fn unpack_bundle(bundle: &Path, destination: &Path) -> Result<(), Error> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(bundle)?));
+    archive.unpack(destination)
+}
+
+/**
+* Name of the CLI release tarball for `arch` in an extracted bundle
+*/
+pub fn find_bundle_asset(bundle_dir: &Path, arch: &str) -> Option<String> {
+    let mut names = fs::read_dir(bundle_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains("linux") && name.contains(arch) && name.ends_with(".tar.gz"))
+        .collect::<Vec<String>>();
+    names.sort();
+
+    names.into_iter().next()
+}
+
+/**
+* Image archives created by `docker save` at the root of an extracted bundle
+*/
+pub fn bundle_image_archives(bundle_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut archives = fs::read_dir(bundle_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "tar"))
+        .collect::<Vec<PathBuf>>();
+    archives.sort();
+
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use crate::utils::release_source::{ReleaseSource, ReleaseSourceKind};
+    use crate::utils::test_server::{route, serve};
+
+    const ASSET: &str = "runtipi-cli-linux-x86_64.tar.gz";
+
+    fn release_dir() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(ASSET), "release content").unwrap();
+        let checksum = sha256_file(&dir.path().join(ASSET)).unwrap();
+
+        (dir, checksum)
+    }
+
+    #[test]
+    fn asset_checksum_file() {
+        let (dir, checksum) = release_dir();
+        fs::write(dir.path().join(format!("{}.sha256", ASSET)), format!("{}\n", checksum)).unwrap();
+
+        assert_eq!(verify_release_dir_with_key(dir.path(), ASSET, None), Ok(()));
+    }
+
+    #[test]
+    fn sha256sums_binary_mode_entry() {
+        let (dir, checksum) = release_dir();
+        let sums = format!(
+            "{}  runtipi-cli-linux-aarch64.tar.gz\n{} *{}\n",
+            "0".repeat(64),
+            checksum.to_uppercase(),
+            ASSET
+        );
+        fs::write(dir.path().join("SHA256SUMS"), sums).unwrap();
+
+        assert_eq!(verify_release_dir_with_key(dir.path(), ASSET, None), Ok(()));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let (dir, _) = release_dir();
+        fs::write(dir.path().join("checksums.txt"), format!("{}  {}\n", "0".repeat(64), ASSET)).unwrap();
+
+        let error = verify_release_dir_with_key(dir.path(), ASSET, None).unwrap_err();
+        assert!(error.starts_with("Checksum mismatch"), "{}", error);
+    }
+
+    #[test]
+    fn missing_checksum_file() {
+        let (dir, _) = release_dir();
+
+        assert_eq!(
+            verify_release_dir_with_key(dir.path(), ASSET, None),
+            Err(format!("No checksum file found for {}", ASSET))
+        );
+    }
+
+    #[test]
+    fn asset_missing_from_checksum_file() {
+        let (dir, checksum) = release_dir();
+        fs::write(dir.path().join("SHA256SUMS"), format!("{}  other.tar.gz\n", checksum)).unwrap();
+
+        let error = verify_release_dir_with_key(dir.path(), ASSET, None).unwrap_err();
+        assert!(error.contains("does not contain a checksum"), "{}", error);
+    }
+
+    fn generate_key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn signatures() {
+        let key_pair = generate_key_pair();
+        let public_key = hex::encode(key_pair.public_key().as_ref());
+        let message = b"checksums";
+        let signature = hex::encode(key_pair.sign(message).as_ref());
+
+        assert_eq!(verify_signature(message, &signature, &public_key), Ok(()));
+        assert!(verify_signature(b"tampered checksums", &signature, &public_key).is_err());
+        assert!(verify_signature(message, &hex::encode(generate_key_pair().sign(message).as_ref()), &public_key).is_err());
+        assert!(verify_signature(message, "not hex", &public_key).is_err());
+    }
+
+    #[test]
+    fn signed_release_dir() {
+        let (dir, checksum) = release_dir();
+        let key_pair = generate_key_pair();
+        let public_key = hex::encode(key_pair.public_key().as_ref());
+        let sums = format!("{}  {}\n", checksum, ASSET);
+        fs::write(dir.path().join("SHA256SUMS"), &sums).unwrap();
+
+        let error = verify_release_dir_with_key(dir.path(), ASSET, Some(&public_key)).unwrap_err();
+        assert!(error.contains("is not signed"), "{}", error);
+
+        let signature_path = dir.path().join("SHA256SUMS.sig");
+        fs::write(&signature_path, hex::encode(key_pair.sign(sums.as_bytes()).as_ref())).unwrap();
+        assert_eq!(verify_release_dir_with_key(dir.path(), ASSET, Some(&public_key)), Ok(()));
+
+        fs::write(&signature_path, hex::encode(generate_key_pair().sign(sums.as_bytes()).as_ref())).unwrap();
+        let error = verify_release_dir_with_key(dir.path(), ASSET, Some(&public_key)).unwrap_err();
+        assert!(error.starts_with("Invalid signature for SHA256SUMS"), "{}", error);
+    }
+
+    fn elf(class: u8, machine: u16) -> Vec<u8> {
+        let mut header = vec![0u8; 64];
+        header[..4].copy_from_slice(b"\x7fELF");
+        header[4] = class;
+        header[5] = 1;
+        header[18..20].copy_from_slice(&machine.to_le_bytes());
+        header
+    }
+
+    fn tarball(dir: &Path, entries: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join(ASSET);
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(File::create(&path).unwrap(), Default::default()));
+
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+        path
+    }
+
+    fn extract(entries: &[(&str, &[u8])], arch: &str) -> Result<Vec<u8>, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        let binary = extract_binary(&tarball(dir.path(), entries), ASSET, arch, destination.path())?;
+        assert_eq!(fs::metadata(&binary).unwrap().permissions().mode() & 0o777, 0o755);
+        assert_eq!(fs::read_dir(destination.path()).unwrap().count(), 1);
+
+        Ok(fs::read(binary).unwrap())
+    }
+
+    #[test]
+    fn extracts_the_binary() {
+        let binary = elf(2, 0x3e);
+        let entries: [(&str, &[u8]); 2] = [("README.md", b"readme"), ("release/runtipi-cli-linux-x86_64", &binary)];
+        assert_eq!(extract(&entries, "x86_64"), Ok(binary.clone()));
+
+        let binary = elf(2, 0xb7);
+        assert_eq!(extract(&[("runtipi-cli", &binary)], "aarch64"), Ok(binary.clone()));
+    }
+
+    #[test]
+    fn refuses_a_binary_for_another_architecture() {
+        let error = extract(&[("runtipi-cli", &elf(2, 0xb7))], "x86_64").unwrap_err();
+        assert!(error.ends_with("is not built for x86_64"), "{}", error);
+
+        let error = extract(&[("runtipi-cli", &elf(2, 0x3e))], "armv7").unwrap_err();
+        assert_eq!(error, "Unsupported architecture armv7");
+    }
+
+    #[test]
+    fn refuses_a_binary_that_is_not_an_elf_executable() {
+        let error = extract(&[("runtipi-cli", b"#!/bin/sh\necho 'not a binary'\n")], "x86_64").unwrap_err();
+        assert!(error.ends_with("is not a 64 bits linux executable"), "{}", error);
+
+        let error = extract(&[("runtipi-cli", &elf(1, 0x3e))], "x86_64").unwrap_err();
+        assert!(error.ends_with("is not a 64 bits linux executable"), "{}", error);
+
+        let error = extract(&[("runtipi-cli", b"\x7fELF")], "x86_64").unwrap_err();
+        assert!(error.ends_with("is not an executable"), "{}", error);
+    }
+
+    #[test]
+    fn refuses_a_tarball_without_binary() {
+        let error = extract(&[("README.md", b"readme"), ("runtipi-cli-linux-aarch64", &elf(2, 0x3e))], "x86_64").unwrap_err();
+        assert_eq!(error, format!("{} does not contain a runtipi-cli-linux-x86_64 binary", ASSET));
+    }
+
+    fn channel_release(channel: ReleaseChannel, releases: &[Release]) -> Option<String> {
+        channel.latest(releases).map(|r| r.version.clone())
+    }
+
+    #[test]
+    fn latest_release_of_each_channel() {
+        let server = serve(vec![route(
+            "/index.json",
+            200,
+            r#"{ "releases": [
+                { "version": "nightly" },
+                { "version": "v3.11.0-beta.2" },
+                { "version": "v3.11.0-beta.10" },
+                { "version": "v3.10.0" },
+                { "version": "v3.9.0" },
+                { "version": "v3.10.0-rc.1" }
+            ] }"#,
+        )]);
+        let source = ReleaseSource {
+            kind: ReleaseSourceKind::Http,
+            url: Some(server),
+            ..Default::default()
+        };
+        let releases = source.fetch_releases().unwrap();
+
+        assert_eq!(channel_release(ReleaseChannel::Stable, &releases).as_deref(), Some("3.10.0"));
+        assert_eq!(channel_release(ReleaseChannel::Beta, &releases).as_deref(), Some("3.11.0-beta.10"));
+
+        let nightly = ReleaseChannel::Nightly.latest(&releases).unwrap();
+        assert_eq!(nightly.version, "nightly");
+        assert_eq!(image_tag(&nightly.version), "nightly");
+    }
+
+    #[test]
+    fn latest_release_without_matching_releases() {
+        let releases = vec![Release {
+            version: "3.1.0-beta.1".to_string(),
+            ..Default::default()
+        }];
+
+        assert_eq!(channel_release(ReleaseChannel::Stable, &releases), None);
+        assert_eq!(channel_release(ReleaseChannel::Beta, &releases).as_deref(), Some("3.1.0-beta.1"));
+        assert_eq!(channel_release(ReleaseChannel::Nightly, &releases), None);
+    }
+
+    #[test]
+    fn image_tags() {
+        assert_eq!(image_tag("3.1.0"), "v3.1.0");
+        assert_eq!(image_tag("v3.1.0-beta.1\n"), "v3.1.0-beta.1");
+        assert_eq!(image_tag("nightly"), "nightly");
+    }
+}
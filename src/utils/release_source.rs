@@ -0,0 +1,344 @@
+use reqwest::Url;
+use self_update::backends::{gitea, github, gitlab};
+use self_update::update::{Release, ReleaseAsset};
+use serde::{Deserialize, Serialize};
+
+// The release source is configured with `releaseSource` in settings.json eg:
+//
+// "releaseSource": { "type": "gitea", "url": "https://git.example.com", "repo": "me/runtipi-cli" }
+//
+// or with the RUNTIPI_RELEASE_SOURCE, RUNTIPI_RELEASE_URL, RUNTIPI_RELEASE_REPO and RUNTIPI_RELEASE_TOKEN
+// environment variables, which take precedence over settings.json
+//
+// settings.json is readable by every user, tokens are better passed with RUNTIPI_RELEASE_TOKEN
+
+const DEFAULT_GITHUB_REPO: &str = "runtipi/cli";
+const DEFAULT_GITLAB_URL: &str = "https://gitlab.com";
+const INDEX_FILE_NAME: &str = "index.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseSourceKind {
+    #[default]
+    Github,
+    Gitea,
+    Gitlab,
+    /// A static HTTP folder with an index.json listing the releases
+    Http,
+}
+
+impl std::str::FromStr for ReleaseSourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "github" => Ok(ReleaseSourceKind::Github),
+            "gitea" => Ok(ReleaseSourceKind::Gitea),
+            "gitlab" => Ok(ReleaseSourceKind::Gitlab),
+            "http" => Ok(ReleaseSourceKind::Http),
+            _ => Err(format!("Unknown release source {}. Expected github, gitea, gitlab or http", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for ReleaseSourceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReleaseSourceKind::Github => write!(f, "github"),
+            ReleaseSourceKind::Gitea => write!(f, "gitea"),
+            ReleaseSourceKind::Gitlab => write!(f, "gitlab"),
+            ReleaseSourceKind::Http => write!(f, "http"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReleaseSource {
+    #[serde(rename = "type", default)]
+    pub kind: ReleaseSourceKind,
+    /// API url for github (eg: https://github.example.com/api/v3), gitea and gitlab. Index or folder url for http
+    pub url: Option<String>,
+    /// Repository as owner/name. Defaults to runtipi/cli on github
+    pub repo: Option<String>,
+    /// Token sent to the API and with downloads, for private repositories and mirrors
+    pub token: Option<String>,
+}
+
+/// Release listed in the index.json of an http source
+#[derive(Debug, Deserialize)]
+struct IndexRelease {
+    version: String,
+    #[serde(default)]
+    date: String,
+    notes: Option<String>,
+    #[serde(default)]
+    assets: Vec<IndexAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IndexAsset {
+    name: String,
+    /// Relative to the index. Defaults to <version>/<name>
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Index {
+    releases: Vec<IndexRelease>,
+}
+
+impl std::fmt::Display for ReleaseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let repo = self.repo.as_deref().unwrap_or(DEFAULT_GITHUB_REPO);
+        let url = self.url.as_deref().unwrap_or_default();
+
+        match self.kind {
+            ReleaseSourceKind::Github if self.url.is_none() => write!(f, "GitHub ({})", repo),
+            ReleaseSourceKind::Github => write!(f, "GitHub ({} on {})", repo, url),
+            ReleaseSourceKind::Gitea => write!(f, "Gitea ({} on {})", repo, url),
+            ReleaseSourceKind::Gitlab => write!(f, "GitLab ({} on {})", repo, self.url.as_deref().unwrap_or(DEFAULT_GITLAB_URL)),
+            ReleaseSourceKind::Http => write!(f, "{}", url),
+        }
+    }
+}
+
+fn env_value(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+impl ReleaseSource {
+    /**
+     * Source configured in settings.json, overridden by the RUNTIPI_RELEASE_* environment variables
+     */
+    pub fn resolve(settings: Option<ReleaseSource>) -> Result<ReleaseSource, String> {
+        let mut source = settings.unwrap_or_default();
+
+        if let Some(kind) = env_value("RUNTIPI_RELEASE_SOURCE") {
+            let kind = kind.parse::<ReleaseSourceKind>()?;
+            // The url and repo of another kind of source are meaningless
+            if kind != source.kind {
+                source = ReleaseSource { kind, ..Default::default() };
+            }
+        }
+
+        source.url = env_value("RUNTIPI_RELEASE_URL").or(source.url);
+        source.repo = env_value("RUNTIPI_RELEASE_REPO").or(source.repo);
+        source.token = env_value("RUNTIPI_RELEASE_TOKEN").or(source.token);
+
+        source.validate()?;
+
+        Ok(source)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match &self.url {
+            Some(url) => {
+                Url::parse(url).map_err(|e| format!("Invalid release source url {}: {}", url, e))?;
+            }
+            None if matches!(self.kind, ReleaseSourceKind::Gitea | ReleaseSourceKind::Http) => {
+                return Err(format!("The {} release source needs an url", self.kind));
+            }
+            None => {}
+        }
+
+        if self.kind != ReleaseSourceKind::Http {
+            self.repo_parts()?;
+        }
+
+        Ok(())
+    }
+
+    fn repo_parts(&self) -> Result<(&str, &str), String> {
+        let repo = match (&self.repo, self.kind) {
+            (Some(repo), _) => repo.as_str(),
+            (None, ReleaseSourceKind::Github) => DEFAULT_GITHUB_REPO,
+            (None, _) => return Err("The release source needs a repo, as owner/name".to_string()),
+        };
+
+        match repo.trim_matches('/').split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() => Ok((owner, name)),
+            _ => Err(format!("Invalid release source repo {}. Expected owner/name", repo)),
+        }
+    }
+
+    /**
+     * Url of the index.json of an http source. The configured url can be the index itself or the folder containing it
+     */
+    fn index_url(&self) -> Result<Url, String> {
+        let url = self.url.as_deref().unwrap_or_default();
+        let url = if url.ends_with(".json") {
+            url.to_string()
+        } else {
+            format!("{}/{}", url.trim_end_matches('/'), INDEX_FILE_NAME)
+        };
+
+        Url::parse(&url).map_err(|e| format!("Invalid release source url {}: {}", url, e))
+    }
+
+    fn fetch_index(&self) -> Result<Vec<Release>, String> {
+        let index_url = self.index_url()?;
+
+        let mut request = reqwest::blocking::Client::new().get(index_url.clone());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Unable to fetch {}: {}", index_url, e))?;
+        let index: Index = response.json().map_err(|e| format!("Invalid release index {}: {}", index_url, e))?;
+
+        index
+            .releases
+            .into_iter()
+            .map(|release| {
+                let assets = release
+                    .assets
+                    .into_iter()
+                    .map(|asset| {
+                        let relative = asset.url.unwrap_or_else(|| format!("{}/{}", release.version, asset.name));
+                        let download_url = index_url.join(&relative).map_err(|e| format!("Invalid url for {}: {}", asset.name, e))?;
+
+                        Ok(ReleaseAsset {
+                            name: asset.name,
+                            download_url: download_url.to_string(),
+                        })
+                    })
+                    .collect::<Result<Vec<ReleaseAsset>, String>>()?;
+
+                Ok(Release {
+                    name: release.version.clone(),
+                    version: release.version.trim_start_matches('v').to_string(),
+                    date: release.date,
+                    body: release.notes,
+                    assets,
+                })
+            })
+            .collect()
+    }
+
+    pub fn fetch_releases(&self) -> Result<Vec<Release>, String> {
+        let token = self.token.as_deref();
+
+        let releases = match self.kind {
+            ReleaseSourceKind::Github => {
+                let (owner, name) = self.repo_parts()?;
+                let mut builder = github::ReleaseList::configure();
+                builder.repo_owner(owner).repo_name(name);
+                if let Some(url) = &self.url {
+                    builder.with_url(url.trim_end_matches('/'));
+                }
+                if let Some(token) = token {
+                    builder.auth_token(token);
+                }
+                builder.build().and_then(|releases| releases.fetch())
+            }
+            ReleaseSourceKind::Gitea => {
+                let (owner, name) = self.repo_parts()?;
+                let mut builder = gitea::ReleaseList::configure();
+                builder
+                    .with_host(self.url.as_deref().unwrap_or_default().trim_end_matches('/'))
+                    .repo_owner(owner)
+                    .repo_name(name);
+                if let Some(token) = token {
+                    builder.auth_token(token);
+                }
+                builder.build().and_then(|releases| releases.fetch())
+            }
+            ReleaseSourceKind::Gitlab => {
+                let (owner, name) = self.repo_parts()?;
+                let mut builder = gitlab::ReleaseList::configure();
+                builder
+                    .with_host(self.url.as_deref().unwrap_or(DEFAULT_GITLAB_URL).trim_end_matches('/'))
+                    .repo_owner(owner)
+                    .repo_name(name);
+                if let Some(token) = token {
+                    builder.auth_token(token);
+                }
+                builder.build().and_then(|releases| releases.fetch())
+            }
+            ReleaseSourceKind::Http => return self.fetch_index(),
+        };
+
+        releases.map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{route, serve};
+
+    const INDEX: &str = r#"{
+        "releases": [
+            {
+                "version": "v3.1.0",
+                "date": "2024-02-01",
+                "notes": "Bug fixes",
+                "assets": [
+                    { "name": "runtipi-cli-linux-x86_64.tar.gz" },
+                    { "name": "runtipi-cli-linux-aarch64.tar.gz", "url": "files/aarch64.tar.gz" }
+                ]
+            },
+            { "version": "3.0.0" }
+        ]
+    }"#;
+
+    fn http_source(url: String) -> ReleaseSource {
+        ReleaseSource {
+            kind: ReleaseSourceKind::Http,
+            url: Some(url),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fetch_http_index() {
+        let server = serve(vec![
+            route("/releases/index.json", 200, INDEX),
+            route("/releases/v3.1.0/runtipi-cli-linux-x86_64.tar.gz", 200, "x86_64 release"),
+        ]);
+
+        let releases = http_source(format!("{}/releases/", server)).fetch_releases().unwrap();
+
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[0].name, "v3.1.0");
+        assert_eq!(releases[0].version, "3.1.0");
+        assert_eq!(releases[0].date, "2024-02-01");
+        assert_eq!(releases[0].body.as_deref(), Some("Bug fixes"));
+        assert_eq!(releases[1].version, "3.0.0");
+        assert!(releases[1].assets.is_empty());
+
+        let assets = &releases[0].assets;
+        assert_eq!(
+            assets[0].download_url,
+            format!("{}/releases/v3.1.0/runtipi-cli-linux-x86_64.tar.gz", server)
+        );
+        assert_eq!(assets[1].download_url, format!("{}/releases/files/aarch64.tar.gz", server));
+
+        let content = reqwest::blocking::get(&assets[0].download_url).unwrap().text().unwrap();
+        assert_eq!(content, "x86_64 release");
+    }
+
+    #[test]
+    fn fetch_http_index_by_file_url() {
+        let server = serve(vec![route("/mirror/releases.json", 200, INDEX)]);
+
+        let releases = http_source(format!("{}/mirror/releases.json", server)).fetch_releases().unwrap();
+
+        assert_eq!(
+            releases[0].assets[0].download_url,
+            format!("{}/mirror/v3.1.0/runtipi-cli-linux-x86_64.tar.gz", server)
+        );
+    }
+
+    #[test]
+    fn missing_http_index() {
+        let server = serve(vec![]);
+
+        let error = http_source(server.clone()).fetch_releases().unwrap_err();
+
+        assert!(error.starts_with(&format!("Unable to fetch {}/index.json", server)), "{}", error);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use super::release::sha256_file;
+
+// Example of a rollback snapshot taken before updating from v3.0.0:
+//
+// rollback/v3.0.0
+// ├── runtipi-cli
+// ├── runtipi-cli.sha256
+// ├── VERSION
+// ├── docker-compose.yml
+// └── .env
+//
+// Snapshots are kept out of state, whose permissions are opened to everyone on start, and are only accessible to
+// their owner: the binary they contain is installed by `update rollback`, usually as root
+
+const SNAPSHOT_BINARY_NAME: &str = "runtipi-cli";
+const SNAPSHOT_CHECKSUM_NAME: &str = "runtipi-cli.sha256";
+const SNAPSHOT_FILES: [&str; 3] = ["VERSION", "docker-compose.yml", ".env"];
+// Snapshots contain a full CLI binary, only keep the most recent ones
+const MAX_SNAPSHOTS: usize = 3;
+
+pub fn rollback_folder(root_folder: &Path) -> PathBuf {
+    root_folder.join("rollback")
+}
+
+/**
+* Snapshot the current CLI binary and instance files so that the update to another version can be reverted
+*/
+pub fn create_snapshot(root_folder: &Path, version: &str) -> Result<PathBuf, Error> {
+    let snapshot = rollback_folder(root_folder).join(version);
+
+    if snapshot.exists() {
+        fs::remove_dir_all(&snapshot)?;
+    }
+    DirBuilder::new().recursive(true).mode(0o700).create(&snapshot)?;
+    fs::set_permissions(rollback_folder(root_folder), Permissions::from_mode(0o700))?;
+    fs::set_permissions(&snapshot, Permissions::from_mode(0o700))?;
+
+    let binary = snapshot.join(SNAPSHOT_BINARY_NAME);
+    fs::copy(std::env::current_exe()?, &binary)?;
+    fs::set_permissions(&binary, Permissions::from_mode(0o700))?;
+    fs::write(snapshot.join(SNAPSHOT_CHECKSUM_NAME), sha256_file(&binary)?)?;
+
+    for file in SNAPSHOT_FILES {
+        let source = root_folder.join(file);
+        if source.exists() {
+            fs::copy(&source, snapshot.join(file))?;
+            fs::set_permissions(snapshot.join(file), Permissions::from_mode(0o600))?;
+        }
+    }
+
+    prune_snapshots(root_folder)?;
+
+    Ok(snapshot)
+}
+
+/**
+* Snapshots sorted from the most recent to the oldest
+*/
+pub fn list_snapshots(root_folder: &Path) -> Result<Vec<(String, PathBuf)>, Error> {
+    let folder = rollback_folder(root_folder);
+    if !folder.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut snapshots = vec![];
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        if entry.path().join(SNAPSHOT_BINARY_NAME).is_file() {
+            let modified = entry.metadata()?.modified()?;
+            snapshots.push((modified, entry.file_name().to_string_lossy().to_string(), entry.path()));
+        }
+    }
+
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.0));
+
+    Ok(snapshots.into_iter().map(|(_, version, path)| (version, path)).collect())
+}
+
+fn prune_snapshots(root_folder: &Path) -> Result<(), Error> {
+    for (_, path) in list_snapshots(root_folder)?.into_iter().skip(MAX_SNAPSHOTS) {
+        fs::remove_dir_all(path)?;
+    }
+
+    Ok(())
+}
+
+/**
+* Path of the binary of a snapshot, once checked that nobody else could have changed it since the snapshot was taken
+*/
+pub fn verified_binary(snapshot: &Path) -> Result<PathBuf, Error> {
+    let binary = snapshot.join(SNAPSHOT_BINARY_NAME);
+
+    for path in [snapshot.parent().unwrap_or(snapshot), snapshot, binary.as_path()] {
+        if fs::metadata(path)?.permissions().mode() & 0o022 != 0 {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is writable by other users, refusing to restore it", path.display()),
+            ));
+        }
+    }
+
+    let expected = fs::read_to_string(snapshot.join(SNAPSHOT_CHECKSUM_NAME)).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Unable to read the checksum of the snapshot binary: {}", e),
+        )
+    })?;
+
+    if sha256_file(&binary)? != expected.trim() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Checksum mismatch for {}, refusing to restore it", binary.display()),
+        ));
+    }
+
+    Ok(binary)
+}
+
+/**
+* Copy the instance files of a snapshot back to the root folder. The binary is replaced separately
+*/
+pub fn restore_snapshot_files(root_folder: &Path, snapshot: &Path) -> Result<(), Error> {
+    for file in SNAPSHOT_FILES {
+        let source = snapshot.join(file);
+        if source.exists() {
+            fs::copy(&source, root_folder.join(file))?;
+        }
+    }
+
+    Ok(())
+}
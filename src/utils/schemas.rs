@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+use super::release::ReleaseChannel;
+use super::release_source::ReleaseSource;
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(untagged)]
 pub enum StringOrInt {
@@ -49,4 +52,67 @@ pub struct SettingsSchema {
 
     #[serde(rename = "localDomain")]
     pub local_domain: Option<String>,
+
+    /// Print a notice after `start` when a new version is available. Checked at most once a day
+    #[serde(rename = "checkForUpdates")]
+    pub check_for_updates: Option<bool>,
+
+    /// Release channel used by `update` and the new version notice: stable, beta or nightly
+    #[serde(rename = "updateChannel")]
+    pub update_channel: Option<ReleaseChannel>,
+
+    /// Where `update` looks for releases, for forks and mirrors. GitHub runtipi/cli by default
+    #[serde(rename = "releaseSource")]
+    pub release_source: Option<ReleaseSource>,
+
+    #[serde(rename = "appsRepoUrl")]
+    pub apps_repo_url: Option<String>,
+
+    #[serde(rename = "dnsIp")]
+    pub dns_ip: Option<String>,
+
+    #[serde(rename = "demoMode")]
+    pub demo_mode: Option<bool>,
+
+    #[serde(rename = "guestDashboard")]
+    pub guest_dashboard: Option<bool>,
+
+    #[serde(rename = "allowErrorMonitoring")]
+    pub allow_error_monitoring: Option<bool>,
+
+    #[serde(rename = "allowAutoThemes")]
+    pub allow_auto_themes: Option<bool>,
+
+    #[serde(rename = "persistTraefikConfig")]
+    pub persist_traefik_config: Option<bool>,
+}
+
+/// Subset of an app's config.json, as found in the apps folder and in the app store repos
+#[derive(Debug, Deserialize)]
+pub struct AppInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub tipi_version: Option<i64>,
+    #[serde(default)]
+    pub form_fields: Vec<FormField>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormField {
+    #[serde(rename = "type")]
+    pub field_type: String,
+    pub label: Option<String>,
+    pub env_variable: String,
+    #[serde(default)]
+    pub required: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub default: Option<serde_json::Value>,
+    #[serde(default)]
+    pub options: Vec<FormFieldOption>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormFieldOption {
+    pub value: String,
 }
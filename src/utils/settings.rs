@@ -0,0 +1,999 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::constants::{
+    DEFAULT_APPS_REPO_URL, DEFAULT_DNS_IP, DEFAULT_DOMAIN, DEFAULT_LOCAL_DOMAIN, DEFAULT_NGINX_PORT, DEFAULT_NGINX_PORT_SSL, DEFAULT_POSTGRES_PORT,
+};
+
+// Keys of state/settings.json known by the CLI. Nested keys are written with a dot eg: releaseSource.url
+// Other keys may be written by the dashboard, they are kept untouched
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    Port,
+    Ip,
+    AbsolutePath,
+    Hostname,
+    Bool,
+    Channel,
+    ReleaseSourceKind,
+    Url,
+    Text,
+}
+
+pub struct SettingDefinition {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    pub description: &'static str,
+    pub default: Option<&'static str>,
+}
+
+pub const SETTINGS: &[SettingDefinition] = &[
+    SettingDefinition {
+        key: "listenIp",
+        kind: SettingKind::Ip,
+        description: "IP address of the host, detected when not set",
+        default: None,
+    },
+    SettingDefinition {
+        key: "port",
+        kind: SettingKind::Port,
+        description: "HTTP port of the dashboard",
+        default: Some(DEFAULT_NGINX_PORT),
+    },
+    SettingDefinition {
+        key: "sslPort",
+        kind: SettingKind::Port,
+        description: "HTTPS port of the dashboard",
+        default: Some(DEFAULT_NGINX_PORT_SSL),
+    },
+    SettingDefinition {
+        key: "storagePath",
+        kind: SettingKind::AbsolutePath,
+        description: "Folder where the app data is stored, the root folder when not set",
+        default: None,
+    },
+    SettingDefinition {
+        key: "postgresPort",
+        kind: SettingKind::Port,
+        description: "Port of the tipi database on the host",
+        default: Some(DEFAULT_POSTGRES_PORT),
+    },
+    SettingDefinition {
+        key: "domain",
+        kind: SettingKind::Hostname,
+        description: "Domain used to expose apps",
+        default: Some(DEFAULT_DOMAIN),
+    },
+    SettingDefinition {
+        key: "localDomain",
+        kind: SettingKind::Hostname,
+        description: "Domain used to expose apps on the local network",
+        default: Some(DEFAULT_LOCAL_DOMAIN),
+    },
+    SettingDefinition {
+        key: "appsRepoUrl",
+        kind: SettingKind::Url,
+        description: "Git repository of the app store",
+        default: Some(DEFAULT_APPS_REPO_URL),
+    },
+    SettingDefinition {
+        key: "dnsIp",
+        kind: SettingKind::Ip,
+        description: "DNS server used by the containers",
+        default: Some(DEFAULT_DNS_IP),
+    },
+    SettingDefinition {
+        key: "demoMode",
+        kind: SettingKind::Bool,
+        description: "Run the dashboard in demo mode",
+        default: Some("false"),
+    },
+    SettingDefinition {
+        key: "guestDashboard",
+        kind: SettingKind::Bool,
+        description: "Let visitors see the dashboard without logging in",
+        default: Some("false"),
+    },
+    SettingDefinition {
+        key: "allowErrorMonitoring",
+        kind: SettingKind::Bool,
+        description: "Send anonymous error reports to the runtipi team",
+        default: Some("false"),
+    },
+    SettingDefinition {
+        key: "allowAutoThemes",
+        kind: SettingKind::Bool,
+        description: "Allow the dashboard to switch themes for special occasions",
+        default: Some("true"),
+    },
+    SettingDefinition {
+        key: "persistTraefikConfig",
+        kind: SettingKind::Bool,
+        description: "Keep changes made to the traefik config instead of overwriting them on start",
+        default: Some("false"),
+    },
+    SettingDefinition {
+        key: "checkForUpdates",
+        kind: SettingKind::Bool,
+        description: "Print a notice after start when a new version is available",
+        default: Some("false"),
+    },
+    SettingDefinition {
+        key: "updateChannel",
+        kind: SettingKind::Channel,
+        description: "Release channel used by update: stable, beta or nightly",
+        default: Some("stable"),
+    },
+    SettingDefinition {
+        key: "releaseSource.type",
+        kind: SettingKind::ReleaseSourceKind,
+        description: "Where update looks for releases: github, gitea, gitlab or http",
+        default: Some("github"),
+    },
+    SettingDefinition {
+        key: "releaseSource.url",
+        kind: SettingKind::Url,
+        description: "API url of the release source, or url of the index for http",
+        default: None,
+    },
+    SettingDefinition {
+        key: "releaseSource.repo",
+        kind: SettingKind::Text,
+        description: "Repository of the release source, as owner/name",
+        default: Some("runtipi/cli"),
+    },
+    SettingDefinition {
+        key: "releaseSource.token",
+        kind: SettingKind::Text,
+        description: "Token used to access the release source. Prefer RUNTIPI_RELEASE_TOKEN, settings.json is readable by every user",
+        default: None,
+    },
+];
+
+pub fn definition(key: &str) -> Option<&'static SettingDefinition> {
+    SETTINGS.iter().find(|definition| definition.key == key)
+}
+
+/**
+* Whether a key holds an object of known keys eg: releaseSource
+*/
+fn is_known_object(key: &str) -> bool {
+    SETTINGS.iter().any(|definition| definition.key.starts_with(&format!("{}.", key)))
+}
+
+fn is_valid_hostname(hostname: &str) -> bool {
+    let is_valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+
+    hostname.len() <= 253 && hostname.split('.').all(is_valid_label)
+}
+
+impl SettingKind {
+    /**
+     * Convert a value given on the command line to its json representation
+     */
+    pub fn parse(&self, raw: &str) -> Result<Value, String> {
+        let value = match self {
+            SettingKind::Port => match raw.trim().parse::<u16>() {
+                Ok(port) => Value::from(port),
+                Err(_) => return Err(format!("{} is not a valid port. Expected a number between 1 and 65535", raw)),
+            },
+            SettingKind::Bool => match raw.trim().to_lowercase().as_str() {
+                "true" | "yes" | "1" => Value::Bool(true),
+                "false" | "no" | "0" => Value::Bool(false),
+                _ => return Err(format!("{} is not a valid boolean. Expected true or false", raw)),
+            },
+            _ => Value::String(raw.trim().to_string()),
+        };
+
+        self.check(&value)?;
+
+        Ok(value)
+    }
+
+    /**
+     * Check the type and value of a setting as found in settings.json
+     */
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        if *self == SettingKind::Port {
+            // Ports are accepted as numbers or strings, like StringOrInt
+            let port = match value {
+                Value::Number(number) => number.as_u64(),
+                Value::String(string) => string.parse::<u64>().ok(),
+                _ => None,
+            };
+
+            return match port {
+                Some(port) if (1..=65535).contains(&port) => Ok(()),
+                _ => Err(format!("{} is not a valid port. Expected a number between 1 and 65535", value)),
+            };
+        }
+
+        if *self == SettingKind::Bool {
+            return match value {
+                Value::Bool(_) => Ok(()),
+                _ => Err(format!("{} is not a boolean. Expected true or false", value)),
+            };
+        }
+
+        let text = match value {
+            Value::String(text) => text.as_str(),
+            _ => return Err(format!("{} is not a string", value)),
+        };
+
+        match self {
+            SettingKind::Ip => text
+                .parse::<IpAddr>()
+                .map(|_| ())
+                .map_err(|_| format!("{} is not a valid IP address", text)),
+            SettingKind::AbsolutePath => {
+                let path = Path::new(text);
+                if !path.is_absolute() {
+                    Err(format!("{} is not an absolute path", text))
+                } else if !path.is_dir() {
+                    Err(format!("{} does not exist or is not a folder", text))
+                } else {
+                    Ok(())
+                }
+            }
+            SettingKind::Hostname if !is_valid_hostname(text) => Err(format!("{} is not a valid hostname", text)),
+            SettingKind::Channel if !["stable", "beta", "nightly"].contains(&text) => {
+                Err(format!("{} is not a release channel. Expected stable, beta or nightly", text))
+            }
+            SettingKind::ReleaseSourceKind if !["github", "gitea", "gitlab", "http"].contains(&text) => {
+                Err(format!("{} is not a release source. Expected github, gitea, gitlab or http", text))
+            }
+            SettingKind::Url => reqwest::Url::parse(text)
+                .map(|_| ())
+                .map_err(|e| format!("{} is not a valid url: {}", text, e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn settings_path(root_folder: &Path) -> PathBuf {
+    root_folder.join("state").join("settings.json")
+}
+
+/**
+* Content of settings.json, with the keys in their original order. An empty object when the file does not exist
+*/
+pub fn read(root_folder: &Path) -> Result<Map<String, Value>, Error> {
+    let content = match std::fs::read_to_string(settings_path(root_folder)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Map::new()),
+        Err(e) => return Err(e),
+    };
+
+    match serde_json::from_str::<Value>(&content) {
+        Ok(Value::Object(settings)) => Ok(settings),
+        Ok(_) => Err(Error::new(ErrorKind::InvalidData, "settings.json must contain a json object")),
+        Err(e) => Err(Error::new(ErrorKind::InvalidData, format!("settings.json is not valid json: {}", e))),
+    }
+}
+
+pub fn write(root_folder: &Path, settings: &Map<String, Value>) -> Result<(), Error> {
+    let path = settings_path(root_folder);
+    let tmp_path = path.with_extension("json.tmp");
+
+    let content = serde_json::to_string_pretty(settings).map_err(Error::other)?;
+    std::fs::create_dir_all(root_folder.join("state"))?;
+    std::fs::write(&tmp_path, format!("{}\n", content))?;
+    std::fs::rename(tmp_path, path)
+}
+
+pub fn get<'a>(settings: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    match key.split_once('.') {
+        Some((parent, child)) => settings.get(parent)?.as_object().and_then(|object| get(object, child)),
+        None => settings.get(key),
+    }
+}
+
+pub fn set(settings: &mut Map<String, Value>, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((parent, child)) => {
+            let entry = settings.entry(parent).or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            if let Value::Object(object) = entry {
+                set(object, child, value);
+            }
+        }
+        None => {
+            settings.insert(key.to_string(), value);
+        }
+    }
+}
+
+/**
+* Remove a key, and the object containing it when it becomes empty. Returns whether the key was set
+*/
+pub fn unset(settings: &mut Map<String, Value>, key: &str) -> bool {
+    match key.split_once('.') {
+        Some((parent, child)) => {
+            let removed = match settings.get_mut(parent) {
+                Some(Value::Object(object)) => unset(object, child),
+                _ => false,
+            };
+
+            if settings.get(parent).and_then(Value::as_object).is_some_and(Map::is_empty) {
+                settings.shift_remove(parent);
+            }
+
+            removed
+        }
+        None => settings.shift_remove(key).is_some(),
+    }
+}
+
+/**
+* Whether a key holds a secret that must be kept out of logs and bug reports eg: releaseSource.token
+*/
+pub fn is_secret(key: &str) -> bool {
+    key.to_lowercase().ends_with("token")
+}
+
+/**
+* Replace the value of every secret, at any depth, with `mask`
+*/
+pub fn redact_secrets(value: &mut Value, mask: &str) {
+    match value {
+        Value::Object(object) => {
+            for (key, child) in object.iter_mut() {
+                if is_secret(key) && !child.is_null() {
+                    *child = Value::String(mask.to_string());
+                } else {
+                    redact_secrets(child, mask);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_secrets(item, mask)),
+        _ => {}
+    }
+}
+
+/**
+* Keys of settings.json unknown to the CLI, most likely written by the dashboard or typos
+*/
+pub fn unknown_keys(settings: &Map<String, Value>) -> Vec<String> {
+    let mut unknown = vec![];
+
+    for (key, value) in settings {
+        match value {
+            Value::Object(object) if is_known_object(key) => {
+                for child in object.keys() {
+                    let full_key = format!("{}.{}", key, child);
+                    if definition(&full_key).is_none() {
+                        unknown.push(full_key);
+                    }
+                }
+            }
+            _ if definition(key).is_none() && !is_known_object(key) => unknown.push(key.clone()),
+            _ => {}
+        }
+    }
+
+    unknown
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Problem found in settings.json, located by its json path eg: $.releaseSource.url
+#[derive(Debug, Serialize)]
+pub struct SettingsIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (line {}, column {}): {}", self.path, self.line, self.column, self.message)
+    }
+}
+
+/**
+* Finds the line and column of every key and array item of a valid json document, by json path
+*/
+struct LocationScanner {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+    locations: HashMap<String, (usize, usize)>,
+}
+
+impl LocationScanner {
+    fn scan(content: &str) -> HashMap<String, (usize, usize)> {
+        let mut scanner = LocationScanner {
+            chars: content.chars().collect(),
+            index: 0,
+            line: 1,
+            column: 1,
+            locations: HashMap::new(),
+        };
+        scanner.skip_whitespace();
+        scanner.locations.insert("$".to_string(), (scanner.line, scanner.column));
+        scanner.value("$");
+
+        scanner.locations
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn hex_escape(&mut self) -> u32 {
+        let digits = (0..4).filter_map(|_| self.next()).collect::<String>();
+        u32::from_str_radix(&digits, 16).unwrap_or_default()
+    }
+
+    /**
+     * Decoded content of the string starting at the current position, the way serde_json decodes keys
+     */
+    fn string(&mut self) -> String {
+        let mut result = String::new();
+        self.next();
+
+        while let Some(c) = self.next() {
+            match c {
+                '"' => break,
+                '\\' => match self.next() {
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.hex_escape();
+
+                        // Characters outside of the basic plane are escaped as a surrogate pair eg: \ud83d\ude00
+                        if (0xd800..0xdc00).contains(&code) && self.chars.get(self.index..self.index + 2) == Some(&['\\', 'u']) {
+                            self.next();
+                            self.next();
+                            let low = self.hex_escape();
+                            code = 0x10000 + ((code - 0xd800) << 10) + low.wrapping_sub(0xdc00);
+                        }
+
+                        result.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    Some(escaped) => result.push(escaped),
+                    None => {}
+                },
+                _ => result.push(c),
+            }
+        }
+
+        result
+    }
+
+    fn value(&mut self, path: &str) {
+        match self.peek() {
+            Some('{') => {
+                self.next();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some('"') => {
+                            let position = (self.line, self.column);
+                            let key = self.string();
+                            let key_path = format!("{}.{}", path, key);
+                            self.locations.insert(key_path.clone(), position);
+
+                            self.skip_whitespace();
+                            self.next(); // :
+                            self.skip_whitespace();
+                            self.value(&key_path);
+                        }
+                        Some(',') => {
+                            self.next();
+                        }
+                        _ => {
+                            self.next(); // }
+                            break;
+                        }
+                    }
+                }
+            }
+            Some('[') => {
+                self.next();
+                let mut item = 0;
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(']') | None => {
+                            self.next();
+                            break;
+                        }
+                        Some(',') => {
+                            self.next();
+                        }
+                        _ => {
+                            let item_path = format!("{}[{}]", path, item);
+                            self.locations.insert(item_path.clone(), (self.line, self.column));
+                            self.value(&item_path);
+                            item += 1;
+                        }
+                    }
+                }
+            }
+            Some('"') => {
+                self.string();
+            }
+            _ => {
+                while self.peek().is_some_and(|c| !matches!(c, ',' | '}' | ']') && !c.is_whitespace()) {
+                    self.next();
+                }
+            }
+        }
+    }
+}
+
+/**
+* Number of single character edits to go from a to b
+*/
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<char>>();
+    let b = b.to_lowercase().chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/**
+* Closest known key to a misspelled one eg: sslport -> sslPort
+*/
+fn suggestion(key: &str) -> Option<String> {
+    let (parent, name) = match key.rsplit_once('.') {
+        Some((parent, name)) => (Some(parent), name),
+        None => (None, key),
+    };
+
+    SETTINGS
+        .iter()
+        .filter_map(|definition| match (parent, definition.key.split_once('.')) {
+            (Some(parent), Some((definition_parent, child))) if parent == definition_parent => Some(child),
+            (None, Some((definition_parent, _))) => Some(definition_parent),
+            (None, None) => Some(definition.key),
+            _ => None,
+        })
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 4))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| match parent {
+            Some(parent) => format!("{}.{}", parent, candidate),
+            None => candidate.to_string(),
+        })
+}
+
+/**
+* Port of a setting, its default when not set. None when invalid
+*/
+fn effective_port(settings: &Map<String, Value>, definition: &SettingDefinition) -> Option<u64> {
+    match get(settings, definition.key) {
+        Some(Value::Number(number)) => number.as_u64(),
+        Some(Value::String(string)) => string.parse().ok(),
+        Some(Value::Null) | None => definition.default.and_then(|default| default.parse().ok()),
+        _ => None,
+    }
+}
+
+/**
+* Every problem of settings.json at once: invalid json, wrong types and values, port collisions, and unknown keys as warnings
+*/
+pub fn check(content: &str) -> Vec<SettingsIssue> {
+    let settings = match serde_json::from_str::<Value>(content) {
+        Ok(Value::Object(settings)) => settings,
+        Ok(_) => {
+            return vec![SettingsIssue {
+                severity: Severity::Error,
+                path: "$".to_string(),
+                line: 1,
+                column: 1,
+                message: "settings.json must contain a json object".to_string(),
+            }]
+        }
+        Err(e) => {
+            return vec![SettingsIssue {
+                severity: Severity::Error,
+                path: "$".to_string(),
+                line: e.line(),
+                column: e.column(),
+                message: format!(
+                    "Invalid json: {}",
+                    e.to_string()
+                        .rsplit_once(" at line ")
+                        .map_or(e.to_string(), |(message, _)| message.to_string())
+                ),
+            }]
+        }
+    };
+
+    let locations = LocationScanner::scan(content);
+    let mut issues = vec![];
+    let mut issue = |severity: Severity, key: &str, message: String| {
+        let path = format!("$.{}", key);
+        let (line, column) = locations.get(&path).copied().unwrap_or((1, 1));
+        issues.push(SettingsIssue {
+            severity,
+            path,
+            line,
+            column,
+            message,
+        });
+    };
+
+    for (key, value) in &settings {
+        if is_known_object(key) && !value.is_object() && !value.is_null() {
+            issue(Severity::Error, key, format!("{} is not an object", value));
+        }
+    }
+
+    for definition in SETTINGS {
+        match get(&settings, definition.key) {
+            // null is how the dashboard unsets a value
+            Some(Value::Null) | None => {}
+            Some(value) => {
+                if let Err(e) = definition.kind.check(value) {
+                    issue(Severity::Error, definition.key, e);
+                }
+            }
+        }
+    }
+
+    let ports = SETTINGS
+        .iter()
+        .filter(|definition| definition.kind == SettingKind::Port)
+        .collect::<Vec<&SettingDefinition>>();
+
+    for (index, definition) in ports.iter().enumerate() {
+        let port = match effective_port(&settings, definition) {
+            Some(port) => port,
+            None => continue,
+        };
+
+        if let Some(other) = ports[..index].iter().find(|other| effective_port(&settings, other) == Some(port)) {
+            issue(Severity::Error, definition.key, format!("Port {} is already used by {}", port, other.key));
+        }
+    }
+
+    for key in unknown_keys(&settings) {
+        let message = match suggestion(&key) {
+            Some(suggestion) => format!("Unknown setting {}. Did you mean {}?", key, suggestion),
+            None => format!("Unknown setting {}, it is ignored by the CLI", key),
+        };
+        issue(Severity::Warning, &key, message);
+    }
+
+    issues.sort_by_key(|issue| (issue.line, issue.column));
+
+    issues
+}
+
+/**
+* Check state/settings.json, no issue when the file does not exist
+*/
+pub fn check_file(root_folder: &Path) -> Result<Vec<SettingsIssue>, Error> {
+    match std::fs::read_to_string(settings_path(root_folder)) {
+        Ok(content) => Ok(check(&content)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/**
+* Error listing every error of the issues, None when there are only warnings
+*/
+pub fn issues_error(issues: &[SettingsIssue]) -> Option<Error> {
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.to_string())
+        .collect::<Vec<String>>();
+
+    if errors.is_empty() {
+        return None;
+    }
+
+    Some(Error::new(
+        ErrorKind::InvalidData,
+        format!("settings.json is invalid:\n - {}", errors.join("\n - ")),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(object) => object,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn get_nested_keys() {
+        let settings = object(json!({ "port": 8080, "releaseSource": { "type": "gitea" }, "domain": "example.com" }));
+
+        assert_eq!(get(&settings, "port"), Some(&json!(8080)));
+        assert_eq!(get(&settings, "releaseSource.type"), Some(&json!("gitea")));
+        assert_eq!(get(&settings, "releaseSource.url"), None);
+        assert_eq!(get(&settings, "domain.url"), None);
+        assert_eq!(get(&settings, "missing"), None);
+    }
+
+    #[test]
+    fn set_keeps_unknown_keys_and_order() {
+        let mut settings = object(json!({ "dashboardOnly": { "a": 1 }, "port": 80, "releaseSource": "invalid" }));
+
+        set(&mut settings, "port", json!(8080));
+        set(&mut settings, "releaseSource.url", json!("https://git.example.com"));
+        set(&mut settings, "domain", json!("example.com"));
+
+        assert_eq!(
+            serde_json::to_string(&settings).unwrap(),
+            r#"{"dashboardOnly":{"a":1},"port":8080,"releaseSource":{"url":"https://git.example.com"},"domain":"example.com"}"#
+        );
+    }
+
+    #[test]
+    fn unset_removes_emptied_objects() {
+        let mut settings = object(json!({ "port": 80, "releaseSource": { "type": "gitea", "url": "https://git.example.com" } }));
+
+        assert!(unset(&mut settings, "releaseSource.url"));
+        assert_eq!(settings["releaseSource"], json!({ "type": "gitea" }));
+
+        assert!(unset(&mut settings, "releaseSource.type"));
+        assert!(!settings.contains_key("releaseSource"));
+
+        assert!(!unset(&mut settings, "releaseSource.type"));
+        assert!(!unset(&mut settings, "port.value"));
+        assert!(unset(&mut settings, "port"));
+        assert!(settings.is_empty());
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(SettingKind::Port.parse(" 8080 "), Ok(json!(8080)));
+        assert_eq!(SettingKind::Bool.parse("yes"), Ok(json!(true)));
+        assert_eq!(SettingKind::Bool.parse("False"), Ok(json!(false)));
+        assert_eq!(SettingKind::Ip.parse("192.168.1.2"), Ok(json!("192.168.1.2")));
+        assert_eq!(SettingKind::Channel.parse("beta"), Ok(json!("beta")));
+        assert_eq!(SettingKind::Hostname.parse("tipi.local"), Ok(json!("tipi.local")));
+        assert_eq!(SettingKind::Text.parse("me/runtipi-cli"), Ok(json!("me/runtipi-cli")));
+        assert_eq!(SettingKind::AbsolutePath.parse("/"), Ok(json!("/")));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_values() {
+        let rejected = [
+            (SettingKind::Port, "abc"),
+            (SettingKind::Port, "0"),
+            (SettingKind::Port, "65536"),
+            (SettingKind::Bool, "maybe"),
+            (SettingKind::Ip, "192.168.1"),
+            (SettingKind::AbsolutePath, "relative/path"),
+            (SettingKind::AbsolutePath, "/does/not/exist"),
+            (SettingKind::Hostname, "-tipi.local"),
+            (SettingKind::Hostname, "tipi..local"),
+            (SettingKind::Channel, "unstable"),
+            (SettingKind::ReleaseSourceKind, "bitbucket"),
+            (SettingKind::Url, "git.example.com"),
+        ];
+
+        for (kind, raw) in rejected {
+            assert!(kind.parse(raw).is_err(), "{:?} {}", kind, raw);
+        }
+    }
+
+    #[test]
+    fn check_value_types() {
+        assert!(SettingKind::Port.check(&json!("8080")).is_ok());
+        assert!(SettingKind::Port.check(&json!(true)).is_err());
+        assert!(SettingKind::Bool.check(&json!("true")).is_err());
+        assert!(SettingKind::Text.check(&json!(1)).is_err());
+    }
+
+    #[test]
+    fn secrets() {
+        assert!(is_secret("releaseSource.token"));
+        assert!(is_secret("apiToken"));
+        assert!(!is_secret("tokenUrl"));
+
+        let mut settings = json!({ "port": 80, "releaseSource": { "token": "secret", "url": "https://git.example.com" }, "apiToken": null });
+        redact_secrets(&mut settings, "***");
+
+        assert_eq!(
+            settings,
+            json!({ "port": 80, "releaseSource": { "token": "***", "url": "https://git.example.com" }, "apiToken": null })
+        );
+    }
+
+    fn issues(content: &str) -> Vec<(Severity, String, usize, usize, String)> {
+        check(content)
+            .into_iter()
+            .map(|issue| (issue.severity, issue.path, issue.line, issue.column, issue.message))
+            .collect()
+    }
+
+    fn issue(severity: Severity, path: &str, line: usize, column: usize, message: &str) -> (Severity, String, usize, usize, String) {
+        (severity, path.to_string(), line, column, message.to_string())
+    }
+
+    #[test]
+    fn check_valid_settings() {
+        assert!(check("{}").is_empty());
+        assert!(check(r#"{ "port": "8080", "sslPort": 8443, "domain": null, "releaseSource": { "type": "gitea" } }"#).is_empty());
+    }
+
+    #[test]
+    fn check_invalid_json() {
+        assert_eq!(
+            issues("{\n  \"port\": 80,\n}"),
+            [issue(Severity::Error, "$", 3, 1, "Invalid json: trailing comma")]
+        );
+        assert_eq!(
+            issues("[]"),
+            [issue(Severity::Error, "$", 1, 1, "settings.json must contain a json object")]
+        );
+    }
+
+    #[test]
+    fn check_locates_every_issue() {
+        let content = r#"{
+  "port": "abc",
+  "demoMode": "yes",
+  "sslport": 444,
+  "releaseSource": {
+    "tpye": "gitea",
+    "url": "git.example.com"
+  },
+  "dashboardOnly": [1, 2]
+}"#;
+
+        assert_eq!(
+            issues(content),
+            [
+                issue(
+                    Severity::Error,
+                    "$.port",
+                    2,
+                    3,
+                    "\"abc\" is not a valid port. Expected a number between 1 and 65535"
+                ),
+                issue(Severity::Error, "$.demoMode", 3, 3, "\"yes\" is not a boolean. Expected true or false"),
+                issue(Severity::Warning, "$.sslport", 4, 3, "Unknown setting sslport. Did you mean sslPort?"),
+                issue(
+                    Severity::Warning,
+                    "$.releaseSource.tpye",
+                    6,
+                    5,
+                    "Unknown setting releaseSource.tpye. Did you mean releaseSource.type?"
+                ),
+                issue(
+                    Severity::Error,
+                    "$.releaseSource.url",
+                    7,
+                    5,
+                    "git.example.com is not a valid url: relative URL without a base"
+                ),
+                issue(
+                    Severity::Warning,
+                    "$.dashboardOnly",
+                    9,
+                    3,
+                    "Unknown setting dashboardOnly, it is ignored by the CLI"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_port_collisions() {
+        assert_eq!(
+            issues("{\n  \"port\": 8080,\n  \"sslPort\": \"8080\"\n}"),
+            [issue(Severity::Error, "$.sslPort", 3, 3, "Port 8080 is already used by port")]
+        );
+        // Unset ports use their default
+        assert_eq!(
+            issues("{\n  \"postgresPort\": 443\n}"),
+            [issue(Severity::Error, "$.postgresPort", 2, 3, "Port 443 is already used by sslPort")]
+        );
+    }
+
+    #[test]
+    fn check_objects() {
+        assert_eq!(
+            issues(r#"{ "releaseSource": "gitea" }"#),
+            [issue(Severity::Error, "$.releaseSource", 1, 3, "\"gitea\" is not an object")]
+        );
+    }
+
+    #[test]
+    fn check_locates_escaped_keys() {
+        let content = "{\n  \"\\u0073slport\": 444,\n  \"tab\\tbed\\ud83d\\ude00\": {\n    \"\\\"quoted\\\"\": 1\n  }\n}";
+
+        let locations = LocationScanner::scan(content);
+        assert_eq!(locations["$.sslport"], (2, 3));
+        assert_eq!(locations["$.tab\tbed😀"], (3, 3));
+        assert_eq!(locations["$.tab\tbed😀.\"quoted\""], (4, 5));
+
+        assert_eq!(
+            issues(content)[0],
+            issue(Severity::Warning, "$.sslport", 2, 3, "Unknown setting sslport. Did you mean sslPort?")
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(suggestion("sslport").as_deref(), Some("sslPort"));
+        assert_eq!(suggestion("listenip").as_deref(), Some("listenIp"));
+        assert_eq!(suggestion("releasesource").as_deref(), Some("releaseSource"));
+        assert_eq!(suggestion("releaseSource.rpeo").as_deref(), Some("releaseSource.repo"));
+        assert_eq!(suggestion("dashboardOnly"), None);
+        assert_eq!(suggestion("other.url"), None);
+
+        assert_eq!(edit_distance("sslport", "sslPort"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "port"), 4);
+    }
+
+    #[test]
+    fn issues_errors() {
+        assert!(issues_error(&check(r#"{ "sslport": 1 }"#)).is_none());
+
+        let error = issues_error(&check(r#"{ "port": "abc", "demoMode": 1 }"#)).unwrap();
+        assert_eq!(
+            error.to_string(),
+            "settings.json is invalid:\n - $.port (line 1, column 3): \"abc\" is not a valid port. Expected a number between 1 and 65535\n - $.demoMode (line 1, column 18): 1 is not a boolean. Expected true or false"
+        );
+    }
+}
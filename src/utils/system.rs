@@ -1,9 +1,10 @@
 use hex::encode;
 
 use sha2::{Digest, Sha256};
-use std::io::{Error, ErrorKind, Write};
+use std::fs::File;
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
 use std::{env, fs};
-use std::{fs::File, path::PathBuf};
 
 use get_if_addrs::get_if_addrs;
 
@@ -38,13 +39,12 @@ pub fn get_internal_ip() -> String {
     "0.0.0.0".to_string()
 }
 
-pub fn get_seed(root_folder: &PathBuf) -> String {
+pub fn get_seed(root_folder: &Path) -> String {
     let seed_file_path = root_folder.join("state").join("seed");
-    let seed = std::fs::read_to_string(&seed_file_path).unwrap_or_default();
-    seed
+    std::fs::read_to_string(seed_file_path).unwrap_or_default()
 }
 
-pub fn derive_entropy(entropy: &str, seed: &String) -> String {
+pub fn derive_entropy(entropy: &str, seed: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(seed);
     hasher.update(entropy);
@@ -58,16 +58,14 @@ pub fn ensure_docker() -> Result<(), Error> {
     match output {
         Ok(output) => {
             if !output.status.success() {
-                return Err(Error::new(
-                    ErrorKind::Other,
+                return Err(Error::other(
                     "Docker is not installed or user has not the right permissions. See https://docs.docker.com/engine/install/ for more information"
                         .to_string(),
                 ));
             }
         }
         Err(_) => {
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Docker is not installed or user has not the right permissions. See https://docs.docker.com/engine/install/ for more information"
                     .to_string(),
             ));
@@ -79,15 +77,13 @@ pub fn ensure_docker() -> Result<(), Error> {
     match output {
         Ok(output) => {
             if !output.status.success() {
-                return Err(Error::new(
-                    ErrorKind::Other,
+                return Err(Error::other(
                     "Docker compose plugin is not installed. See https://docs.docker.com/compose/install/linux/ for more information".to_string(),
                 ));
             }
         }
         Err(_) => {
-            return Err(Error::new(
-                ErrorKind::Other,
+            return Err(Error::other(
                 "Docker compose plugin is not installed. See https://docs.docker.com/compose/install/linux/ for more information".to_string(),
             ));
         }
@@ -122,6 +118,20 @@ pub fn copy_system_files() -> Result<(), Error> {
     Ok(())
 }
 
+/**
+* Ids of the apps installed in the apps folder, sorted
+*/
+pub fn list_installed_apps(root_folder: &Path) -> Result<Vec<String>, Error> {
+    let mut apps = fs::read_dir(root_folder.join("apps"))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    apps.sort();
+
+    Ok(apps)
+}
+
 pub fn ensure_file_permissions() -> Result<(), Error> {
     let root_folder: PathBuf = env::current_dir().expect("Unable to get current directory");
 
@@ -143,10 +153,10 @@ pub fn ensure_file_permissions() -> Result<(), Error> {
             let chmod_status = std::process::Command::new("chmod").arg("-Rf").arg(perms).arg(&full_path).output()?;
 
             if !chmod_status.status.success() {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("{} has incorrect permissions. Please run the CLI as root to fix this.", path),
-                ));
+                return Err(Error::other(format!(
+                    "{} has incorrect permissions. Please run the CLI as root to fix this.",
+                    path
+                )));
             }
         }
     }
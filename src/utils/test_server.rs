@@ -0,0 +1,78 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+// Minimal HTTP server for the tests of the clients talking to the worker and to release sources.
+// Each route is answered with a fixed status and body, any other path gets a 404
+
+pub struct Route {
+    pub path: String,
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+pub fn route(path: &str, status: u16, body: impl Into<Vec<u8>>) -> Route {
+    Route {
+        path: path.to_string(),
+        status,
+        body: body.into(),
+    }
+}
+
+/**
+* Start a server on a random local port and return its base url eg: http://127.0.0.1:41234
+*/
+pub fn serve(routes: Vec<Route>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+
+            // Consume the headers and the body so the client doesn't get a reset
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            let _ = reader.read_exact(&mut body);
+
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match routes.iter().find(|route| route.path == path) {
+                Some(route) => (route.status, route.body.as_slice()),
+                None => (404, b"".as_slice()),
+            };
+
+            let head = format!("HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(body);
+        }
+    });
+
+    url
+}
+
+/**
+* Url of a local port nothing listens on
+*/
+pub fn closed_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::constants::DEFAULT_NGINX_PORT;
+use super::env::env_string_to_map;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// App operations (install, update, reset...) are run synchronously by the worker and can take a while
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstallAppRequest<'a> {
+    pub form: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+/// Body returned by the worker for app operations
+#[derive(Debug, Deserialize)]
+pub struct AppActionResponse {
+    #[serde(default = "default_success")]
+    pub success: bool,
+    pub message: Option<String>,
+}
+
+// An empty 2xx body is treated as a success
+impl Default for AppActionResponse {
+    fn default() -> Self {
+        AppActionResponse {
+            success: true,
+            message: None,
+        }
+    }
+}
+
+fn default_success() -> bool {
+    true
+}
+
+/// Body returned by the worker when a request fails
+#[derive(Debug, Default, Deserialize)]
+pub struct ApiErrorBody {
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ApiErrorBody {
+    fn from_response(response: Response) -> ApiErrorBody {
+        let text = response.text().unwrap_or_default();
+
+        serde_json::from_str(&text).unwrap_or(ApiErrorBody {
+            message: if text.trim().is_empty() { None } else { Some(text.trim().to_string()) },
+            error: None,
+        })
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.message.as_deref().or(self.error.as_deref())
+    }
+}
+
+#[derive(Debug)]
+pub enum WorkerApiError {
+    /// The .env file is missing or doesn't contain what we need to reach the worker
+    Config(String),
+    /// Nothing is listening on the worker address. Tipi is most likely stopped
+    ConnectionRefused(String),
+    Timeout(String),
+    Unauthorized(ApiErrorBody),
+    NotFound(ApiErrorBody),
+    Server(StatusCode, ApiErrorBody),
+    Unexpected(StatusCode, ApiErrorBody),
+    Request(reqwest::Error),
+    /// The request went through but the worker reported that the operation failed
+    Failed(Option<String>),
+}
+
+impl fmt::Display for WorkerApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let with_reason = |f: &mut fmt::Formatter<'_>, message: String, body: &ApiErrorBody| match body.reason() {
+            Some(reason) => write!(f, "{}: {}", message, reason),
+            None => write!(f, "{}", message),
+        };
+
+        match self {
+            WorkerApiError::Config(message) => write!(f, "{}", message),
+            WorkerApiError::ConnectionRefused(url) => {
+                write!(f, "Unable to reach the worker at {}. Make sure tipi is running", url)
+            }
+            WorkerApiError::Timeout(url) => write!(f, "The worker at {} took too long to respond", url),
+            WorkerApiError::Unauthorized(body) => with_reason(
+                f,
+                "The worker rejected the request (401). Check that JWT_SECRET in your .env matches the running instance".to_string(),
+                body,
+            ),
+            WorkerApiError::NotFound(body) => with_reason(f, "Not found (404)".to_string(), body),
+            WorkerApiError::Server(status, body) => {
+                with_reason(f, format!("The worker failed to process the request ({})", status), body)?;
+                write!(f, ". See logs/error.log for more details.")
+            }
+            WorkerApiError::Unexpected(status, body) => with_reason(f, format!("Unexpected response from the worker ({})", status), body),
+            WorkerApiError::Request(err) => write!(f, "{}", err),
+            WorkerApiError::Failed(message) => match message {
+                Some(message) => write!(f, "{}", message),
+                None => write!(f, "The worker reported a failure. See logs/error.log for more details."),
+            },
+        }
+    }
+}
+
+impl std::error::Error for WorkerApiError {}
+
+pub struct WorkerApi {
+    base_url: String,
+    jwt_secret: String,
+    client: Client,
+}
+
+impl WorkerApi {
+    pub fn new(base_url: &str, jwt_secret: &str) -> Result<WorkerApi, WorkerApiError> {
+        let client = Client::builder()
+            .user_agent(concat!("runtipi-cli/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(WorkerApiError::Request)?;
+
+        Ok(WorkerApi {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            jwt_secret: jwt_secret.to_string(),
+            client,
+        })
+    }
+
+    /**
+     * Build a client pointing at the worker behind the reverse proxy, using the values of the .env file in the root folder
+     */
+    pub fn from_root_folder(root_folder: &Path) -> Result<WorkerApi, WorkerApiError> {
+        let env_file = std::fs::read_to_string(root_folder.join(".env"))
+            .map_err(|e| WorkerApiError::Config(format!("Unable to read .env file: {}. Make sure tipi has been started at least once", e)))?;
+        let env_map = env_string_to_map(&env_file);
+
+        let internal_ip = match env_map.get("INTERNAL_IP").map(|ip| ip.as_str()) {
+            None | Some("") | Some("0.0.0.0") => "localhost",
+            Some(ip) => ip,
+        };
+        let port = env_map.get("NGINX_PORT").map(|p| p.as_str()).unwrap_or(DEFAULT_NGINX_PORT);
+        let jwt_secret = env_map.get("JWT_SECRET").map(|s| s.as_str()).unwrap_or("secret");
+
+        WorkerApi::new(&format!("http://{}:{}/worker-api", internal_ip, port), jwt_secret)
+    }
+
+    pub fn from_current_dir() -> Result<WorkerApi, WorkerApiError> {
+        let root_folder = std::env::current_dir().map_err(|e| WorkerApiError::Config(format!("Unable to get current directory: {}", e)))?;
+        WorkerApi::from_root_folder(&root_folder)
+    }
+
+    fn token(&self) -> Result<String, WorkerApiError> {
+        let claims = Claims { sub: "1".to_string() };
+        let encoding_key = EncodingKey::from_secret(self.jwt_secret.as_ref());
+
+        encode(&Header::new(Algorithm::HS256), &claims, &encoding_key)
+            .map_err(|e| WorkerApiError::Config(format!("Unable to create worker token: {}", e)))
+    }
+
+    fn execute(&self, request: RequestBuilder, url: &str) -> Result<Response, WorkerApiError> {
+        let response = request.header("Authorization", format!("Bearer {}", self.token()?)).send().map_err(|e| {
+            if e.is_timeout() {
+                WorkerApiError::Timeout(url.to_string())
+            } else if e.is_connect() {
+                WorkerApiError::ConnectionRefused(url.to_string())
+            } else {
+                WorkerApiError::Request(e)
+            }
+        })?;
+
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = ApiErrorBody::from_response(response);
+
+        Err(match status {
+            StatusCode::UNAUTHORIZED => WorkerApiError::Unauthorized(body),
+            StatusCode::NOT_FOUND => WorkerApiError::NotFound(body),
+            s if s.is_server_error() => WorkerApiError::Server(s, body),
+            s => WorkerApiError::Unexpected(s, body),
+        })
+    }
+
+    fn send<T: DeserializeOwned + Default>(&self, request: RequestBuilder, url: &str) -> Result<T, WorkerApiError> {
+        let response = self.execute(request, url)?;
+        let status = response.status();
+
+        let text = response.text().map_err(WorkerApiError::Request)?;
+        if text.trim().is_empty() {
+            return Ok(T::default());
+        }
+
+        serde_json::from_str(&text).map_err(|e| {
+            WorkerApiError::Unexpected(
+                status,
+                ApiErrorBody {
+                    message: Some(format!("invalid response body ({})", e)),
+                    error: None,
+                },
+            )
+        })
+    }
+
+    fn post<B: Serialize, T: DeserializeOwned + Default>(&self, path: &str, body: Option<&B>) -> Result<T, WorkerApiError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut request = self.client.post(&url);
+
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        self.send(request, &url)
+    }
+
+    /**
+     * Check that the worker is up and reachable through the reverse proxy
+     */
+    pub fn healthcheck(&self) -> Result<(), WorkerApiError> {
+        let url = format!("{}/healthcheck", self.base_url);
+        self.execute(self.client.get(&url), &url).map(|_| ())
+    }
+
+    fn app_action(&self, id: &str, action: &str) -> Result<AppActionResponse, WorkerApiError> {
+        self.post::<(), _>(&format!("/apps/{}/{}", id, action), None)
+    }
+
+    pub fn start_app(&self, id: &str) -> Result<AppActionResponse, WorkerApiError> {
+        self.app_action(id, "start")
+    }
+
+    pub fn stop_app(&self, id: &str) -> Result<AppActionResponse, WorkerApiError> {
+        self.app_action(id, "stop")
+    }
+
+    pub fn uninstall_app(&self, id: &str) -> Result<AppActionResponse, WorkerApiError> {
+        self.app_action(id, "uninstall")
+    }
+
+    pub fn reset_app(&self, id: &str) -> Result<AppActionResponse, WorkerApiError> {
+        self.app_action(id, "reset")
+    }
+
+    pub fn update_app(&self, id: &str) -> Result<AppActionResponse, WorkerApiError> {
+        self.app_action(id, "update")
+    }
+
+    pub fn install_app(&self, id: &str, request: &InstallAppRequest) -> Result<AppActionResponse, WorkerApiError> {
+        self.post(&format!("/apps/{}/install", id), Some(request))
+    }
+
+    pub fn start_all_apps(&self) -> Result<AppActionResponse, WorkerApiError> {
+        self.post::<(), _>("/apps/start-all", None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{closed_url, route, serve};
+
+    #[test]
+    fn connection_refused() {
+        let api = WorkerApi::new(&closed_url(), "secret").unwrap();
+
+        assert!(matches!(api.healthcheck(), Err(WorkerApiError::ConnectionRefused(_))));
+    }
+
+    #[test]
+    fn unauthorized() {
+        let api = WorkerApi::new(&serve(vec![route("/healthcheck", 401, "")]), "secret").unwrap();
+
+        assert!(matches!(api.healthcheck(), Err(WorkerApiError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn not_found() {
+        let api = WorkerApi::new(&serve(vec![]), "secret").unwrap();
+
+        match api.start_app("nope") {
+            Err(WorkerApiError::NotFound(body)) => assert_eq!(body.reason(), None),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn server_error_with_message() {
+        let url = serve(vec![route("/apps/nginx/start", 500, r#"{"message":"Container failed to start"}"#)]);
+        let api = WorkerApi::new(&url, "secret").unwrap();
+
+        match api.start_app("nginx") {
+            Err(error @ WorkerApiError::Server(status, _)) => {
+                assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+                assert!(error.to_string().contains("Container failed to start"));
+            }
+            other => panic!("expected Server, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn server_error_with_text_body() {
+        let api = WorkerApi::new(&serve(vec![route("/apps/nginx/stop", 502, "Bad Gateway")]), "secret").unwrap();
+
+        match api.stop_app("nginx") {
+            Err(WorkerApiError::Server(status, body)) => {
+                assert_eq!(status, StatusCode::BAD_GATEWAY);
+                assert_eq!(body.reason(), Some("Bad Gateway"));
+            }
+            other => panic!("expected Server, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_success_body() {
+        let api = WorkerApi::new(&serve(vec![route("/apps/nginx/reset", 200, "")]), "secret").unwrap();
+
+        let response = api.reset_app("nginx").unwrap();
+        assert!(response.success);
+        assert_eq!(response.message, None);
+    }
+
+    #[test]
+    fn reported_failure() {
+        let url = serve(vec![route(
+            "/apps/nginx/update",
+            200,
+            r#"{"success":false,"message":"No update available"}"#,
+        )]);
+        let api = WorkerApi::new(&url, "secret").unwrap();
+
+        let response = api.update_app("nginx").unwrap();
+        assert!(!response.success);
+        assert_eq!(response.message.as_deref(), Some("No update available"));
+    }
+
+    #[test]
+    fn invalid_success_body() {
+        let api = WorkerApi::new(&serve(vec![route("/apps/nginx/start", 200, "<html>")]), "secret").unwrap();
+
+        assert!(matches!(api.start_app("nginx"), Err(WorkerApiError::Unexpected(StatusCode::OK, _))));
+    }
+}